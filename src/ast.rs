@@ -1,6 +1,6 @@
 #![allow(dead_code)]
-use crate::token::Token;
-use std::{any::Any, fmt::{Debug, Display}};
+use crate::token::{Span, Token};
+use std::{any::Any, fmt::{Debug, Display}, rc::Rc};
 
 pub trait Node: Display {
     fn token_literal(&self) -> String;
@@ -13,6 +13,13 @@ pub trait StatementTrait: Node {
 pub trait Expression: Node + Debug {
     fn expression_node(&self);
     fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    // Where runtime errors raised by this expression are reported: the
+    // operator token for operations, the name for identifiers.
+    fn span(&self) -> Option<Span> {
+        None
+    }
 }
 
 
@@ -20,16 +27,45 @@ pub trait Expression: Node + Debug {
 pub enum Statement {
     Let {
         token: Token,
-        name : Option<Identifier>,
-        value: Option<Box<dyn Expression>>
+        name : Identifier,
+        value: Box<dyn Expression>
     },
     Return {
         token: Token,
-        value: Option<Box<dyn Expression>>
+        value: Box<dyn Expression>
     },
     Expression {
         token: Token,
-        expression: Option<Box<dyn Expression>>
+        expression: Box<dyn Expression>
+    },
+    While {
+        token: Token,
+        condition: Box<dyn Expression>,
+        body: BlockStatement
+    },
+    For {
+        token: Token,
+        variable: Identifier,
+        iterable: Box<dyn Expression>,
+        body: BlockStatement
+    },
+    Break {
+        token: Token
+    },
+    Continue {
+        token: Token
+    },
+    Throw {
+        token: Token,
+        value: Box<dyn Expression>,
+        // The `throw` keyword, where the raised error is reported.
+        span: Span,
+    },
+    // Tokens the parser skipped after it could not make a statement of them.
+    // `token` is the first of them and `span` covers them all.
+    Error {
+        token: Token,
+        span: Span,
     }
 }
 
@@ -37,8 +73,14 @@ impl Node for Statement {
     fn token_literal(&self) -> String {
         match self {
             Self::Return{token:_, value: _} => "return".to_string(),
-            Self::Let{token: _, name:_, value:_}  => "let".to_string(),
+            Self::Let{token, name:_, value:_}  => token.get_literal(),
             Self::Expression { token, expression: _ } => token.get_literal().to_string(),
+            Self::While { token, .. } => token.get_literal(),
+            Self::For { token, .. } => token.get_literal(),
+            Self::Break { token } => token.get_literal(),
+            Self::Continue { token } => token.get_literal(),
+            Self::Throw { token, .. } => token.get_literal(),
+            Self::Error { token, .. } => token.get_literal(),
         }
     }
 }
@@ -46,32 +88,27 @@ impl Node for Statement {
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Expression { token:_, expression } => {
-                let mut str = String::from("");
-                if let Some(exp) = expression {
-                    str = format!("{}", exp);
-                }
-                write!(f, "{}", str)
+            Self::Expression { token:_, expression } => write!(f, "{}", expression),
+
+            Self::Let { token, name, value  } => write!(f, "{} {} = {};", &token.get_literal(), &name, &value),
+
+            Self::Return { token, value } => write!(f, "{} {};", &token.get_literal(), &value),
+
+            Self::While { token: _, condition, body } => {
+                write!(f, "while ({}) {}", condition, body)
             },
 
-            Self::Let { token, name, value  } => {
-                let mut str = String::new();
-                if let Some(name) = name {
-                    str = format!("{} {}",&token.get_literal(), &name.token_literal());
-                    if let Some(val) = value {
-                        str = format!("{} {} = {};",&token.get_literal(), &name, &val);
-                    }
-                }
-                write!(f, "{}", str)
+            Self::For { token: _, variable, iterable, body } => {
+                write!(f, "for ({} in {}) {}", variable, iterable, body)
             },
 
-            Self::Return { token, value } => {
-                let mut str = String::from("return;");
-                if let Some(val) = value {
-                    str = format!("{} {};", &token.get_literal(), &val);
-                }
-                write!(f, "{}", str)
-            }
+            Self::Break { token } => write!(f, "{};", token.get_literal()),
+
+            Self::Continue { token } => write!(f, "{};", token.get_literal()),
+
+            Self::Throw { token, value, .. } => write!(f, "{} {};", token.get_literal(), value),
+
+            Self::Error { .. } => write!(f, "<error>"),
         }
     }
 }
@@ -80,20 +117,11 @@ impl StatementTrait for Statement {
     fn statement_node(&self) {}
 }
 
-impl Statement {
-    pub fn new(token: Token) -> Option<Self> {
-        match token {
-            Token::Let => Some(Self::Let { token, name: None, value: None }),
-            Token::Return => Some(Self::Return { token, value: None }),
-            _ => None
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Identifier {
     pub token: Token,
     pub value: String,
+    pub span: Span,
 }
 
 impl Node for Identifier {
@@ -109,6 +137,14 @@ impl Expression for Identifier {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl Display for Identifier {
@@ -118,19 +154,91 @@ impl Display for Identifier {
 }
 
 impl Identifier {
-    pub fn new(token: Token) -> Self {
+    pub fn new(token: Token, span: Span) -> Self {
         Self {
             token: token.clone(),
             value: token.get_literal(),
+            span,
         }
     }
 }
 
 
+// Where an expression was expected but the source ends it before one starts,
+// as in `let x = ;`. The span is empty and sits where the expression would be.
+#[derive(Debug)]
+pub struct MissingExpression {
+    pub span: Span,
+}
+
+impl Node for MissingExpression {
+    fn token_literal(&self) -> String {
+        String::new()
+    }
+}
+
+impl Expression for MissingExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl Display for MissingExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<missing>")
+    }
+}
+
+// An expression that starts but could not be parsed, such as `(1 + 2`.
+// `token` is its first token and `span` covers the tokens read for it.
+#[derive(Debug)]
+pub struct ErrorExpression {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl Node for ErrorExpression {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Expression for ErrorExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl Display for ErrorExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<error>")
+    }
+}
+
 #[derive(Debug)]
 pub struct IntegerLiteral {
     pub token: Token,
-    pub value: i32,
+    pub value: i64,
 }
 
 impl Node for IntegerLiteral {
@@ -147,6 +255,10 @@ impl Expression for IntegerLiteral {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 impl Display for IntegerLiteral {
@@ -156,7 +268,7 @@ impl Display for IntegerLiteral {
 }
 
 impl IntegerLiteral {
-    pub fn new(token: Token, value: i32) -> Self {
+    pub fn new(token: Token, value: i64) -> Self {
         Self {
             token: token.clone(),
             value
@@ -164,6 +276,36 @@ impl IntegerLiteral {
     }
 }
 
+#[derive(Debug)]
+pub struct FloatLiteral {
+    pub token: Token,
+    pub value: f64,
+}
+
+impl Node for FloatLiteral {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Expression for FloatLiteral {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Display for FloatLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.value)
+    }
+}
+
 #[derive(Debug)]
 pub struct Boolean {
     pub token: Token,
@@ -178,6 +320,10 @@ impl Expression for Boolean {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 impl Node for Boolean {
@@ -193,12 +339,186 @@ impl Display for Boolean {
 }
 
 
+// String Literal
+#[derive(Debug)]
+pub struct StringLiteral {
+    pub token: Token,
+    pub value: String
+}
+
+impl Expression for StringLiteral {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Node for StringLiteral {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Display for StringLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.value)
+    }
+}
+
+// Array Literal
+#[derive(Debug)]
+pub struct ArrayLiteral {
+    pub token: Token,
+    pub elements: Vec<Box<dyn Expression>>
+}
+
+impl Expression for ArrayLiteral {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Display for ArrayLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]", self.elements.iter().map(|el| el.to_string()).collect::<Vec<String>>().join(", "))
+    }
+}
+
+// Index Expression
+#[derive(Debug)]
+pub struct IndexExpression {
+    pub token: Token,
+    pub left: Box<dyn Expression>,
+    pub index: Box<dyn Expression>,
+    pub span: Span,
+}
+
+impl Expression for IndexExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl Node for IndexExpression {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Display for IndexExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}[{}])", self.left, self.index)
+    }
+}
+
+// Member Expression: `left.property`, currently only valid on modules.
+#[derive(Debug)]
+pub struct MemberExpression {
+    pub token: Token,
+    pub left: Box<dyn Expression>,
+    pub property: Identifier,
+    pub span: Span,
+}
+
+impl Expression for MemberExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl Node for MemberExpression {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Display for MemberExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}.{})", self.left, self.property)
+    }
+}
+
+// Import Expression: `import "path.mk"`, with the path relative to the
+// importing file.
+#[derive(Debug)]
+pub struct ImportExpression {
+    pub token: Token,
+    pub path: String,
+    pub span: Span,
+}
+
+impl Expression for ImportExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl Node for ImportExpression {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
+impl Display for ImportExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "import {:?}", self.path)
+    }
+}
+
 // Prefix Expression
 #[derive(Debug)]
 pub struct PrefixExpression {
     pub token: Token,
     pub operator: String,
-    pub right: Box<dyn Expression>
+    pub right: Box<dyn Expression>,
+    pub span: Span,
 }
 
 impl Expression for PrefixExpression {
@@ -208,6 +528,14 @@ impl Expression for PrefixExpression {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl Display for PrefixExpression {
@@ -229,6 +557,7 @@ pub struct InfixExpression {
     pub left: Box<dyn Expression>,
     pub right: Box<dyn Expression>,
     pub operator: String,
+    pub span: Span,
 }
 
 impl Expression for InfixExpression {
@@ -238,6 +567,14 @@ impl Expression for InfixExpression {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl Display for InfixExpression {
@@ -252,6 +589,51 @@ impl Node for InfixExpression {
     }
 }
 
+// Assign Expression
+//
+// `target = value` (and the compound `+=`, `-=`, `*=`, `/=` forms) rebinds an
+// existing name: it updates the nearest binding found by walking outwards
+// from the current scope, so a closure can mutate a variable of an enclosing
+// function. Assigning to a name that was never bound is a runtime error.
+// `let` is the only way to introduce a new binding and always shadows.
+// The target may also be an index expression, `arr[i] = value`.
+#[derive(Debug)]
+pub struct AssignExpression {
+    pub token: Token,
+    pub target: Box<dyn Expression>,
+    pub operator: String,
+    pub value: Box<dyn Expression>,
+    pub span: Span,
+}
+
+impl Expression for AssignExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
+}
+
+impl Display for AssignExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({} {} {})", self.target, self.operator, self.value)
+    }
+}
+
+impl Node for AssignExpression {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
 // If Expression
 #[derive(Debug)]
 pub struct IfExpression {
@@ -267,6 +649,10 @@ impl Expression for IfExpression {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 impl Display for IfExpression {
@@ -285,12 +671,60 @@ impl Node for  IfExpression{
     }
 }
 
+// `try { } catch (e) { } finally { }`. At least one of the catch and
+// finally clauses is present.
+#[derive(Debug)]
+pub struct TryExpression {
+    pub token: Token,
+    pub block: BlockStatement,
+    pub catch: Option<CatchClause>,
+    pub finally: Option<BlockStatement>,
+}
+
+#[derive(Debug)]
+pub struct CatchClause {
+    pub parameter: Identifier,
+    pub body: BlockStatement,
+}
+
+impl Expression for TryExpression {
+    fn expression_node(&self) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Display for TryExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "try {}", self.block)?;
+        if let Some(catch) = &self.catch {
+            write!(f, " catch ({}) {}", catch.parameter, catch.body)?;
+        }
+        if let Some(finally) = &self.finally {
+            write!(f, " finally {}", finally)?;
+        }
+        Ok(())
+    }
+}
+
+impl Node for TryExpression {
+    fn token_literal(&self) -> String {
+        self.token.get_literal()
+    }
+}
+
 // Call Expression
 #[derive(Debug)]
 pub struct CallExpression {
     pub token: Token,
-    pub function: Option<Box<dyn Expression>>,
-    pub arguments: Vec<Box<dyn Expression>>
+    pub function: Box<dyn Expression>,
+    pub arguments: Vec<Box<dyn Expression>>,
+    pub span: Span,
 }
 
 impl Expression for CallExpression {
@@ -302,11 +736,19 @@ impl Expression for CallExpression {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+
+    fn span(&self) -> Option<Span> {
+        Some(self.span)
+    }
 }
 
 impl Display for CallExpression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.function.as_ref().unwrap())?;
+        write!(f, "{}", self.function)?;
         write!(f, "(")?;
         write!(f, "{}, ", self.arguments.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().join(", "))?;
         write!(f, ")")?;
@@ -326,7 +768,9 @@ impl Node for CallExpression {
 pub struct FunctionLiteral {
     pub token: Token,
     pub parameters: Vec<Identifier>,
-    pub body: BlockStatement
+    pub body: Rc<BlockStatement>,
+    // The name of the `let` binding the literal is the value of, if any.
+    pub name: Option<String>,
 }
 
 impl Expression for FunctionLiteral{
@@ -335,6 +779,10 @@ impl Expression for FunctionLiteral{
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 impl Display for FunctionLiteral {
@@ -379,7 +827,7 @@ impl Node for BlockStatement {
 }
 
 // Program
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
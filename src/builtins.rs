@@ -0,0 +1,229 @@
+#![allow(dead_code)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::evaluator::Evaluator;
+use crate::object::{BuiltinFunction, Elements, ErrorKind, Object};
+
+// Wraps a builtin that reports errors through `Err`, so arguments can be
+// checked with `?`, into a `BuiltinFunction`.
+macro_rules! builtin {
+    ($function:ident) => {
+        |_, args| $function(&args).unwrap_or_else(|err| err)
+    };
+    (evaluator $function:ident) => {
+        |evaluator, args| $function(evaluator, &args).unwrap_or_else(|err| err)
+    };
+}
+
+mod collections;
+mod io;
+mod math;
+mod strings;
+
+const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("len", builtin!(len)),
+    ("first", builtin!(first)),
+    ("last", builtin!(last)),
+    ("rest", builtin!(rest)),
+    ("push", builtin!(push)),
+    ("puts", builtin!(evaluator puts)),
+    ("type", builtin!(type_of)),
+];
+
+pub fn registry() -> HashMap<String, Object> {
+    BUILTINS.iter()
+        .chain(strings::BUILTINS)
+        .chain(collections::BUILTINS)
+        .chain(math::BUILTINS)
+        .chain(io::BUILTINS)
+        .map(|(name, function)| (name.to_string(), Object::Builtin { name, function: *function }))
+        .chain(math::CONSTANTS.iter().map(|(name, value)| (name.to_string(), Object::Float(*value))))
+        .collect()
+}
+
+fn expect_arity(args: &[Object], want: usize) -> Result<(), Object> {
+    if args.len() != want {
+        return Err(Object::wrong_arity(args.len(), want));
+    }
+    Ok(())
+}
+
+fn expect_arity_range(args: &[Object], min: usize, max: usize) -> Result<(), Object> {
+    if args.len() < min || args.len() > max {
+        return Err(Object::wrong_arity(args.len(), format!("{}-{}", min, max)));
+    }
+    Ok(())
+}
+
+fn expect_string<'a>(name: &str, arg: &'a Object) -> Result<&'a str, Object> {
+    match arg {
+        Object::String(value) => Ok(value),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be STRING, got {}", name, other.type_name()))),
+    }
+}
+
+fn expect_integer(name: &str, arg: &Object) -> Result<i64, Object> {
+    match arg {
+        Object::Integer(value) => Ok(*value),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be INTEGER, got {}", name, other.type_name()))),
+    }
+}
+
+fn expect_elements(name: &str, arg: &Object) -> Result<Rc<RefCell<Elements>>, Object> {
+    match arg {
+        Object::Array(elements) => Ok(Rc::clone(elements)),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be ARRAY, got {}", name, other.type_name()))),
+    }
+}
+
+fn len(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    match &args[0] {
+        Object::String(value) => Ok(Object::Integer(value.chars().count() as i64)),
+        Object::Array(elements) => Ok(Object::Integer(elements.borrow().len() as i64)),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `len` not supported, got {}", other.type_name()))),
+    }
+}
+
+fn first(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let elements = expect_elements("first", &args[0])?;
+    let first = elements.borrow().first().cloned();
+    Ok(first.unwrap_or(Object::Null))
+}
+
+fn last(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let elements = expect_elements("last", &args[0])?;
+    let last = elements.borrow().last().cloned();
+    Ok(last.unwrap_or(Object::Null))
+}
+
+// Returns a new array without the first element, or null for an empty array.
+fn rest(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let elements = expect_elements("rest", &args[0])?;
+    let rest = match elements.borrow().split_first() {
+        Some((_, tail)) => Object::new_array(tail.to_vec()),
+        None => Object::Null,
+    };
+    Ok(rest)
+}
+
+// Returns a new array with the element appended; the argument is unchanged.
+fn push(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let mut elements = expect_elements("push", &args[0])?.borrow().to_vec();
+    elements.push(args[1].clone());
+    Ok(Object::new_array(elements))
+}
+
+fn puts(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    for arg in args {
+        writeln!(evaluator.output(), "{}", arg)
+            .map_err(|err| Object::error_of(ErrorKind::Io, format!("could not write output: {}", err)))?;
+    }
+    Ok(Object::Null)
+}
+
+fn type_of(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    Ok(Object::String(args[0].type_name().to_string()))
+}
+
+#[cfg(test)]
+mod testing {
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::object::{Environment, Object};
+    use crate::parser::Parser;
+
+    pub(super) fn eval_with(evaluator: &mut Evaluator, input: &str) -> Object {
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser has errors: {:?}", p.errors());
+        let env = Environment::new();
+        evaluator.eval_program(&program, &env)
+    }
+
+    pub(super) fn eval(input: &str) -> Object {
+        eval_with(&mut Evaluator::new(), input)
+    }
+
+    // Compares each result as printed, with a string result quoted so it
+    // can be told apart from other values.
+    pub(super) fn check(tests: &[(&str, &str)]) {
+        for (input, expected) in tests {
+            let got = match eval(input) {
+                Object::String(value) => format!("{:?}", value),
+                other => other.to_string(),
+            };
+            assert_eq!(got, *expected, "wrong result for {}", input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::evaluator::Evaluator;
+    use crate::object::Object;
+
+    use super::testing::{check, eval, eval_with};
+
+    #[test]
+    fn test_builtin_functions(){
+        let tests = [
+            ("len(\"\")", "0"),
+            ("len(\"four\")", "4"),
+            ("len(\"héllo\")", "5"),
+            ("len([1, 2, 3])", "3"),
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            ("last([1, 2, 3])", "3"),
+            ("last([])", "null"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+            ("let a = [1]; let b = push(a, 2); [a, b]", "[[1], [1, 2]]"),
+            ("puts(\"hello\")", "null"),
+            ("type(1)", "\"INTEGER\""),
+            ("type(1.5)", "\"FLOAT\""),
+            ("type(\"a\")", "\"STRING\""),
+            ("type([])", "\"ARRAY\""),
+            ("type(fn() {})", "\"FUNCTION\""),
+            ("type(len)", "\"BUILTIN\""),
+            ("let len = fn(x) { 42 }; len(\"a\")", "42"),
+        ];
+
+        check(&tests);
+    }
+
+    #[test]
+    fn test_puts_writes_to_output(){
+        let mut evaluator = Evaluator::new();
+        let captured = evaluator.capture_output();
+        assert_eq!(eval_with(&mut evaluator, "puts(1, \"two\"); puts([3])"), Object::Null);
+        assert_eq!(String::from_utf8(captured.borrow().clone()).unwrap(), "1\ntwo\n[3]\n");
+    }
+
+    #[test]
+    fn test_builtin_errors(){
+        let tests = [
+            ("len(1)", "argument to `len` not supported, got INTEGER"),
+            ("len(\"one\", \"two\")", "wrong number of arguments. got=2, want=1"),
+            ("first(1)", "argument to `first` must be ARRAY, got INTEGER"),
+            ("last(\"a\")", "argument to `last` must be ARRAY, got STRING"),
+            ("rest(true)", "argument to `rest` must be ARRAY, got BOOLEAN"),
+            ("push(1, 1)", "argument to `push` must be ARRAY, got INTEGER"),
+            ("push([])", "wrong number of arguments. got=1, want=2"),
+            ("type()", "wrong number of arguments. got=0, want=1"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval(input), Object::error(expected), "wrong result for {}", input);
+        }
+    }
+}
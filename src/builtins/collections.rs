@@ -0,0 +1,409 @@
+use std::cmp::Ordering;
+
+use crate::evaluator::{eval_binary_operation, Evaluator};
+use crate::object::{BuiltinFunction, ErrorKind, Object};
+
+use super::{expect_arity, expect_arity_range, expect_integer};
+use super::math::compare_numbers;
+
+pub const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("map", builtin!(evaluator map)),
+    ("filter", builtin!(evaluator filter)),
+    ("reduce", builtin!(evaluator reduce)),
+    ("sort", builtin!(evaluator sort)),
+    ("sort_by", builtin!(evaluator sort_by)),
+    ("zip", builtin!(evaluator zip)),
+    ("enumerate", builtin!(evaluator enumerate)),
+    ("range", builtin!(evaluator range)),
+    ("any", builtin!(evaluator any)),
+    ("all", builtin!(evaluator all)),
+    ("sum", builtin!(evaluator sum)),
+];
+
+fn expect_array(name: &str, arg: &Object) -> Result<Vec<Object>, Object> {
+    match arg {
+        Object::Array(elements) => Ok(elements.borrow().to_vec()),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be ARRAY, got {}", name, other.type_name()))),
+    }
+}
+
+fn expect_function(name: &str, arg: &Object) -> Result<Object, Object> {
+    match arg {
+        Object::Function { .. } | Object::Builtin { .. } | Object::Native(_) => Ok(arg.clone()),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be FUNCTION, got {}", name, other.type_name()))),
+    }
+}
+
+// Calls back into a user function; an error raised inside it aborts the
+// builtin and is returned unchanged. Each call is a step, even of a builtin.
+fn call(evaluator: &mut Evaluator, function: &Object, args: Vec<Object>) -> Result<Object, Object> {
+    evaluator.charge(1)?;
+    let result = evaluator.apply_function(function.clone(), args);
+    if result.is_error() {
+        return Err(result);
+    }
+    Ok(result)
+}
+
+fn map(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let elements = expect_array("map", &args[0])?;
+    let function = expect_function("map", &args[1])?;
+    let mut result = Vec::with_capacity(elements.len());
+    for el in elements {
+        result.push(call(evaluator, &function, vec![el])?);
+    }
+    Ok(Object::new_array(result))
+}
+
+fn filter(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let elements = expect_array("filter", &args[0])?;
+    let function = expect_function("filter", &args[1])?;
+    let mut result = vec![];
+    for el in elements {
+        if call(evaluator, &function, vec![el.clone()])?.is_truthy() {
+            result.push(el);
+        }
+    }
+    Ok(Object::new_array(result))
+}
+
+// `reduce(arr, f, initial)` folds with `f(acc, el)`. Without an initial value
+// the first element is used, and an empty array is an error.
+fn reduce(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 2, 3)?;
+    let mut elements = expect_array("reduce", &args[0])?.into_iter();
+    let function = expect_function("reduce", &args[1])?;
+    let mut acc = match args.get(2) {
+        Some(initial) => initial.clone(),
+        None => elements.next().ok_or_else(|| Object::error_of(ErrorKind::Argument, "reduce of empty array with no initial value".to_string()))?,
+    };
+    for el in elements {
+        acc = call(evaluator, &function, vec![acc, el])?;
+    }
+    Ok(acc)
+}
+
+fn compare(left: &Object, right: &Object) -> Result<Ordering, Object> {
+    if let Some(ordering) = compare_numbers(left, right) {
+        return Ok(ordering);
+    }
+    match (left, right) {
+        (Object::String(a), Object::String(b)) => Ok(a.cmp(b)),
+        (Object::Boolean(a), Object::Boolean(b)) => Ok(a.cmp(b)),
+        (a, b) => Err(Object::error_of(ErrorKind::Type, format!("cannot compare {} and {}", a.type_name(), b.type_name()))),
+    }
+}
+
+// Stable merge sort with a comparison that may fail. `slice::sort_by` is not
+// used because a user comparator that is not a total order may make it panic.
+fn merge_sort<F>(elements: Vec<Object>, compare: &mut F) -> Result<Vec<Object>, Object>
+where
+    F: FnMut(&Object, &Object) -> Result<Ordering, Object>,
+{
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+
+    let mut left = elements;
+    let right = left.split_off(left.len() / 2);
+    let left = merge_sort(left, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut result = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if compare(r, l)? == Ordering::Less {
+            result.extend(right.next());
+        } else {
+            result.extend(left.next());
+        }
+    }
+    result.extend(left);
+    result.extend(right);
+    Ok(result)
+}
+
+// `sort(arr)` orders numbers, strings or booleans. `sort(arr, cmp)` uses
+// `cmp(a, b)`, which returns a negative integer, zero or a positive integer.
+fn sort(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 1, 2)?;
+    let elements = expect_array("sort", &args[0])?;
+    let sorted = match args.get(1) {
+        Some(comparator) => {
+            let comparator = expect_function("sort", comparator)?;
+            merge_sort(elements, &mut |a, b| {
+                match call(evaluator, &comparator, vec![a.clone(), b.clone()])? {
+                    Object::Integer(ordering) => Ok(ordering.cmp(&0)),
+                    other => Err(Object::error_of(ErrorKind::Type, format!("comparator passed to `sort` must return INTEGER, got {}", other.type_name()))),
+                }
+            })?
+        },
+        None => merge_sort(elements, &mut |a, b| {
+            evaluator.charge(1)?;
+            compare(a, b)
+        })?,
+    };
+    Ok(Object::new_array(sorted))
+}
+
+// Sorts by the key `f(el)`, computing each key once.
+fn sort_by(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let elements = expect_array("sort_by", &args[0])?;
+    let function = expect_function("sort_by", &args[1])?;
+    let mut keyed = Vec::with_capacity(elements.len());
+    for el in elements {
+        let key = call(evaluator, &function, vec![el.clone()])?;
+        keyed.push(Object::new_array(vec![key, el]));
+    }
+
+    let key = |pair: &Object| match pair {
+        Object::Array(pair) => pair.borrow()[0].clone(),
+        _ => unreachable!("sort_by pairs are arrays"),
+    };
+    let sorted = merge_sort(keyed, &mut |a, b| {
+        evaluator.charge(1)?;
+        compare(&key(a), &key(b))
+    })?;
+    Ok(Object::new_array(sorted.iter().map(|pair| match pair {
+        Object::Array(pair) => pair.borrow()[1].clone(),
+        _ => unreachable!("sort_by pairs are arrays"),
+    }).collect()))
+}
+
+fn zip(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let left = expect_array("zip", &args[0])?;
+    let right = expect_array("zip", &args[1])?;
+    evaluator.charge(left.len().min(right.len()) as u64)?;
+    Ok(Object::new_array(left.into_iter().zip(right).map(|(a, b)| Object::new_array(vec![a, b])).collect()))
+}
+
+fn enumerate(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let elements = expect_array("enumerate", &args[0])?;
+    evaluator.charge(elements.len() as u64)?;
+    Ok(Object::new_array(elements.into_iter().enumerate().map(|(i, el)| Object::new_array(vec![Object::Integer(i as i64), el])).collect()))
+}
+
+// `range(end)`, `range(start, end)` or `range(start, end, step)`; `end` is
+// exclusive and a negative step counts down.
+fn range(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 1, 3)?;
+    let numbers = args.iter().map(|arg| expect_integer("range", arg)).collect::<Result<Vec<i64>, Object>>()?;
+    let (start, end, step) = match numbers[..] {
+        [end] => (0, end, 1),
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step),
+        _ => unreachable!("arity is checked"),
+    };
+    if step == 0 {
+        return Err(Object::error_of(ErrorKind::Argument, "argument to `range` must not be a zero step".to_string()));
+    }
+    let len = (end as i128 - start as i128 + step as i128 - step.signum() as i128) / step as i128;
+    evaluator.check_allocation(usize::try_from(len.max(0)).unwrap_or(usize::MAX))?;
+
+    let mut result = vec![];
+    let mut current = start;
+    while (step > 0 && current < end) || (step < 0 && current > end) {
+        evaluator.charge(1)?;
+        result.push(Object::Integer(current));
+        current = match current.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    Ok(Object::new_array(result))
+}
+
+// `any(arr)` and `all(arr)` test truthiness; with a predicate they test
+// `f(el)` and stop at the first element that decides the result.
+fn any(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 1, 2)?;
+    let elements = expect_array("any", &args[0])?;
+    let predicate = args.get(1).map(|f| expect_function("any", f)).transpose()?;
+    for el in elements {
+        let value = match &predicate {
+            Some(predicate) => call(evaluator, predicate, vec![el])?,
+            None => el,
+        };
+        if value.is_truthy() {
+            return Ok(Object::Boolean(true));
+        }
+    }
+    Ok(Object::Boolean(false))
+}
+
+fn all(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 1, 2)?;
+    let elements = expect_array("all", &args[0])?;
+    let predicate = args.get(1).map(|f| expect_function("all", f)).transpose()?;
+    for el in elements {
+        let value = match &predicate {
+            Some(predicate) => call(evaluator, predicate, vec![el])?,
+            None => el,
+        };
+        if !value.is_truthy() {
+            return Ok(Object::Boolean(false));
+        }
+    }
+    Ok(Object::Boolean(true))
+}
+
+// Sums numbers with the same promotion rules as `+`.
+fn sum(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let mut total = Object::Integer(0);
+    for el in expect_array("sum", &args[0])? {
+        evaluator.charge(1)?;
+        if !matches!(el, Object::Integer(_) | Object::Float(_)) {
+            return Err(Object::error_of(ErrorKind::Type, format!("cannot sum {}", el.type_name())));
+        }
+        total = eval_binary_operation("+", total, el);
+        if total.is_error() {
+            return Err(total);
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builtins::testing::check;
+
+    #[test]
+    fn test_map(){
+        check(&[
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([], fn(x) { x })", "[]"),
+            ("map([\"a\", \"bc\"], len)", "[1, 2]"),
+            ("let k = 10; map([1, 2], fn(x) { x + k })", "[11, 12]"),
+            ("map([1, 0, 2], fn(x) { 10 / x })", "ERROR: division by zero"),
+            ("map([1], 5)", "ERROR: argument to `map` must be FUNCTION, got INTEGER"),
+            ("map(1, fn(x) { x })", "ERROR: argument to `map` must be ARRAY, got INTEGER"),
+            ("map([1], fn(x, y) { x })", "ERROR: wrong number of arguments. got=1, want=2"),
+        ]);
+    }
+
+    #[test]
+    fn test_filter(){
+        check(&[
+            ("filter([1, 2, 3, 4], fn(x) { x % 2 == 0 })", "[2, 4]"),
+            ("filter([1, 2], fn(x) { false })", "[]"),
+            ("filter([1, 2], fn(x) { x + true })", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+        ]);
+    }
+
+    #[test]
+    fn test_reduce(){
+        check(&[
+            ("reduce([1, 2, 3, 4], fn(acc, x) { acc + x })", "10"),
+            ("reduce([1, 2, 3], fn(acc, x) { acc * x }, 10)", "60"),
+            ("reduce([], fn(acc, x) { acc + x }, 0)", "0"),
+            ("reduce([\"a\", \"b\"], fn(acc, x) { acc + x }, \">\")", "\">ab\""),
+            ("reduce([], fn(acc, x) { acc + x })", "ERROR: reduce of empty array with no initial value"),
+            ("reduce([1, 2], fn(acc, x) { y })", "ERROR: identifier not found: y"),
+        ]);
+    }
+
+    #[test]
+    fn test_sort(){
+        check(&[
+            ("sort([3, 1, 2])", "[1, 2, 3]"),
+            ("sort([\"pear\", \"apple\", \"fig\"])", "[apple, fig, pear]"),
+            ("sort([2, 0.5, 1])", "[0.5, 1, 2]"),
+            ("sort([])", "[]"),
+            ("sort([3, 1, 2], fn(a, b) { b - a })", "[3, 2, 1]"),
+            ("let a = [2, 1]; sort(a); a", "[2, 1]"),
+            ("sort([1, \"a\"])", "ERROR: cannot compare STRING and INTEGER"),
+            ("sort([1, 2], fn(a, b) { true })", "ERROR: comparator passed to `sort` must return INTEGER, got BOOLEAN"),
+            ("sort([1, 2], fn(a, b) { a / 0 })", "ERROR: division by zero"),
+        ]);
+    }
+
+    #[test]
+    fn test_sort_is_stable(){
+        check(&[
+            ("map(sort([[1, \"b\"], [0, \"x\"], [1, \"a\"]], fn(a, b) { a[0] - b[0] }), fn(p) { p[1] })", "[x, b, a]"),
+        ]);
+    }
+
+    #[test]
+    fn test_sort_by(){
+        check(&[
+            ("sort_by([\"ccc\", \"a\", \"bb\"], len)", "[a, bb, ccc]"),
+            ("sort_by([[2, \"x\"], [1, \"y\"]], fn(p) { p[0] })", "[[1, y], [2, x]]"),
+            ("sort_by([3, 1], fn(x) { -x })", "[3, 1]"),
+            ("sort_by([1, 2], fn(x) { [x] })", "ERROR: cannot compare ARRAY and ARRAY"),
+        ]);
+    }
+
+    #[test]
+    fn test_zip(){
+        check(&[
+            ("zip([1, 2, 3], [\"a\", \"b\"])", "[[1, a], [2, b]]"),
+            ("zip([], [1])", "[]"),
+            ("zip([1], 2)", "ERROR: argument to `zip` must be ARRAY, got INTEGER"),
+        ]);
+    }
+
+    #[test]
+    fn test_enumerate(){
+        check(&[
+            ("enumerate([\"a\", \"b\"])", "[[0, a], [1, b]]"),
+            ("enumerate([])", "[]"),
+        ]);
+    }
+
+    #[test]
+    fn test_range(){
+        check(&[
+            ("range(4)", "[0, 1, 2, 3]"),
+            ("range(2, 5)", "[2, 3, 4]"),
+            ("range(0, 10, 3)", "[0, 3, 6, 9]"),
+            ("range(5, 0, -2)", "[5, 3, 1]"),
+            ("range(5, 0)", "[]"),
+            ("range(0, 1, 0)", "ERROR: argument to `range` must not be a zero step"),
+            ("range(\"a\")", "ERROR: argument to `range` must be INTEGER, got STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_any_and_all(){
+        check(&[
+            ("any([false, 0])", "true"),
+            ("any([])", "false"),
+            ("any([1, 2, 3], fn(x) { x > 2 })", "true"),
+            ("any([1, 2, 3], fn(x) { x > 3 })", "false"),
+            ("all([])", "true"),
+            ("all([1, true])", "true"),
+            ("all([1, 2, 3], fn(x) { x > 1 })", "false"),
+            ("all([1, 2], fn(x) { x / 0 })", "ERROR: division by zero"),
+            ("any([1, 2], fn(x) { x == 1 || x / 0 })", "true"),
+        ]);
+    }
+
+    #[test]
+    fn test_sum(){
+        check(&[
+            ("sum([1, 2, 3])", "6"),
+            ("sum([])", "0"),
+            ("sum(range(101))", "5050"),
+            ("sum([1, 2.5])", "3.5"),
+            ("sum([1, \"a\"])", "ERROR: cannot sum STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_error_kinds(){
+        check(&[
+            ("try { reduce([], fn(a, b) { a + b }) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { range(0, 5, 0) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { map(1, len) } catch (e) { e.kind }", "\"TypeError\""),
+            ("try { sum([\"a\"]) } catch (e) { e.kind }", "\"TypeError\""),
+        ]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::Path;
+
+use crate::evaluator::Evaluator;
+use crate::object::{BuiltinFunction, ErrorKind, Object};
+
+use super::{expect_arity, expect_string};
+
+pub const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("read_file", builtin!(evaluator read_file)),
+    ("write_file", builtin!(evaluator write_file)),
+    ("list_dir", builtin!(evaluator list_dir)),
+    ("getenv", builtin!(evaluator getenv)),
+    ("args", builtin!(evaluator args)),
+];
+
+fn denied(name: &str, flag: &str, path: &str) -> Object {
+    Object::error_of(ErrorKind::Permission, format!("permission denied: `{}` needs {} access to {:?}", name, flag, path))
+}
+
+fn read_file(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let path = expect_string("read_file", &args[0])?;
+    if !evaluator.capabilities().read.allows(Path::new(path)) {
+        return Err(denied("read_file", "--allow-read", path));
+    }
+    fs::read_to_string(path)
+        .map(Object::String)
+        .map_err(|err| Object::error_of(ErrorKind::Io, format!("could not read {:?}: {}", path, err)))
+}
+
+// `write_file(path, contents)` creates or truncates the file.
+fn write_file(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let path = expect_string("write_file", &args[0])?;
+    let contents = expect_string("write_file", &args[1])?;
+    if !evaluator.capabilities().write.allows(Path::new(path)) {
+        return Err(denied("write_file", "--allow-write", path));
+    }
+    fs::write(path, contents)
+        .map(|_| Object::Null)
+        .map_err(|err| Object::error_of(ErrorKind::Io, format!("could not write {:?}: {}", path, err)))
+}
+
+// Returns the entry names of a directory, sorted.
+fn list_dir(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let path = expect_string("list_dir", &args[0])?;
+    if !evaluator.capabilities().read.allows(Path::new(path)) {
+        return Err(denied("list_dir", "--allow-read", path));
+    }
+    let error = |err: std::io::Error| Object::error_of(ErrorKind::Io, format!("could not list {:?}: {}", path, err));
+    let mut names = vec![];
+    for entry in fs::read_dir(path).map_err(error)? {
+        names.push(entry.map_err(error)?.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(Object::new_array(names.into_iter().map(Object::String).collect()))
+}
+
+// Returns null for an unset variable.
+fn getenv(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let name = expect_string("getenv", &args[0])?;
+    if !evaluator.capabilities().env {
+        return Err(Object::error_of(ErrorKind::Permission, format!("permission denied: `getenv` needs --allow-env access to {:?}", name)));
+    }
+    Ok(std::env::var(name).map(Object::String).unwrap_or(Object::Null))
+}
+
+// The arguments the host passed to the script, which are empty by default.
+fn args(evaluator: &mut Evaluator, args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 0)?;
+    Ok(Object::new_array(evaluator.capabilities().args.iter().cloned().map(Object::String).collect()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::builtins::testing::eval_with;
+    use crate::capabilities::Capabilities;
+    use crate::evaluator::Evaluator;
+    use crate::object::Object;
+
+    fn test_eval(input: &str, capabilities: Capabilities) -> Object {
+        eval_with(&mut Evaluator::with_capabilities(capabilities), input)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monkey_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_denied_by_default(){
+        let tests = [
+            ("read_file(\"a.txt\")", "permission denied: `read_file` needs --allow-read access to \"a.txt\""),
+            ("write_file(\"a.txt\", \"\")", "permission denied: `write_file` needs --allow-write access to \"a.txt\""),
+            ("list_dir(\".\")", "permission denied: `list_dir` needs --allow-read access to \".\""),
+            ("getenv(\"HOME\")", "permission denied: `getenv` needs --allow-env access to \"HOME\""),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input, Capabilities::default()), Object::error(expected), "wrong result for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_read_and_write_files(){
+        let dir = temp_dir("io");
+        let data = dir.join("data");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        let mut capabilities = Capabilities::default();
+        capabilities.read.grant(Some(&data));
+        capabilities.write.grant(Some(&data));
+
+        let file = data.join("out.txt");
+        let input = format!("write_file({:?}, \"hello\"); read_file({:?})", file.to_str().unwrap(), file.to_str().unwrap());
+        assert_eq!(test_eval(&input, capabilities.clone()).to_string(), "hello");
+
+        let input = format!("list_dir({:?})", data.to_str().unwrap());
+        assert_eq!(test_eval(&input, capabilities.clone()).to_string(), "[out.txt]");
+
+        let outside = data.join("..").join("secret.txt");
+        let input = format!("read_file({:?})", outside.to_str().unwrap());
+        assert!(test_eval(&input, capabilities.clone()).to_string().starts_with("ERROR: permission denied: `read_file`"));
+
+        let missing = data.join("missing.txt");
+        let input = format!("read_file({:?})", missing.to_str().unwrap());
+        assert!(test_eval(&input, capabilities).to_string().starts_with("ERROR: could not read"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_getenv_and_args(){
+        let capabilities = Capabilities {
+            env: true,
+            args: vec!["one".to_string(), "two".to_string()],
+            ..Capabilities::default()
+        };
+        assert_eq!(test_eval("getenv(\"MONKEY_SURELY_UNSET_VARIABLE\")", capabilities.clone()), Object::Null);
+        assert_eq!(test_eval("args()", capabilities).to_string(), "[one, two]");
+        assert_eq!(test_eval("args()", Capabilities::default()).to_string(), "[]");
+    }
+}
@@ -0,0 +1,315 @@
+use std::cmp::Ordering;
+
+use crate::evaluator::eval_binary_operation;
+use crate::object::{BuiltinFunction, ErrorKind, Object};
+
+use super::{expect_arity, expect_integer};
+
+pub const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("abs", builtin!(abs)),
+    ("min", builtin!(min)),
+    ("max", builtin!(max)),
+    ("pow", builtin!(pow)),
+    ("sqrt", builtin!(sqrt)),
+    ("floor", builtin!(floor)),
+    ("ceil", builtin!(ceil)),
+    ("round", builtin!(round)),
+    ("gcd", builtin!(gcd)),
+    ("clamp", builtin!(clamp)),
+    ("int", builtin!(int)),
+    ("float", builtin!(float)),
+];
+
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("PI", std::f64::consts::PI),
+    ("E", std::f64::consts::E),
+];
+
+fn expect_number(name: &str, arg: &Object) -> Result<f64, Object> {
+    match arg {
+        Object::Integer(value) => Ok(*value as f64),
+        Object::Float(value) => Ok(*value),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be a number, got {}", name, other.type_name()))),
+    }
+}
+
+// Orders two numbers, comparing an integer with a float as floats.
+pub fn compare_numbers(left: &Object, right: &Object) -> Option<Ordering> {
+    match (left, right) {
+        (Object::Integer(a), Object::Integer(b)) => Some(a.cmp(b)),
+        (Object::Integer(a), Object::Float(b)) => (*a as f64).partial_cmp(b),
+        (Object::Float(a), Object::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Object::Float(a), Object::Float(b)) => a.partial_cmp(b),
+        _ => None,
+    }
+}
+
+fn float_to_integer(name: &str, value: f64) -> Result<Object, Object> {
+    if value.is_nan() || value < i64::MIN as f64 || value >= i64::MAX as f64 {
+        return Err(Object::error_of(ErrorKind::Arithmetic, format!("result of `{}` does not fit in an INTEGER: {:?}", name, value)));
+    }
+    Ok(Object::Integer(value as i64))
+}
+
+fn abs(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    match &args[0] {
+        Object::Integer(value) => value.checked_abs().map(Object::Integer).ok_or_else(|| Object::error_of(ErrorKind::Arithmetic, "integer overflow".to_string())),
+        Object::Float(value) => Ok(Object::Float(value.abs())),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `abs` must be a number, got {}", other.type_name()))),
+    }
+}
+
+// `min` and `max` take two or more numbers, or a single array of numbers,
+// and return the winning argument unchanged.
+fn extremum(name: &str, args: &[Object], want: Ordering) -> Result<Object, Object> {
+    let values = match args {
+        [Object::Array(elements)] => elements.borrow().to_vec(),
+        [] => return Err(Object::wrong_arity(0, "1 or more")),
+        _ => args.to_vec(),
+    };
+
+    let mut values = values.into_iter();
+    let mut best = values.next().ok_or_else(|| Object::error_of(ErrorKind::Argument, format!("argument to `{}` must not be an empty array", name)))?;
+    expect_number(name, &best)?;
+    for value in values {
+        expect_number(name, &value)?;
+        match compare_numbers(&value, &best) {
+            Some(ordering) if ordering == want => best = value,
+            Some(_) => {},
+            None => return Err(Object::error_of(ErrorKind::Type, format!("cannot compare {} and {}", value.type_name(), best.type_name()))),
+        }
+    }
+    Ok(best)
+}
+
+fn min(args: &[Object]) -> Result<Object, Object> {
+    extremum("min", args, Ordering::Less)
+}
+
+fn max(args: &[Object]) -> Result<Object, Object> {
+    extremum("max", args, Ordering::Greater)
+}
+
+fn pow(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    expect_number("pow", &args[0])?;
+    expect_number("pow", &args[1])?;
+    let result = eval_binary_operation("**", args[0].clone(), args[1].clone());
+    if result.is_error() {
+        return Err(result);
+    }
+    Ok(result)
+}
+
+fn sqrt(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let value = expect_number("sqrt", &args[0])?;
+    if value < 0.0 {
+        return Err(Object::error_of(ErrorKind::Argument, "argument to `sqrt` must not be negative".to_string()));
+    }
+    Ok(Object::Float(value.sqrt()))
+}
+
+// `floor`, `ceil` and `round` return integers; `round` rounds halves away
+// from zero.
+fn rounding(name: &str, args: &[Object], f: fn(f64) -> f64) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    match &args[0] {
+        Object::Integer(value) => Ok(Object::Integer(*value)),
+        Object::Float(value) => float_to_integer(name, f(*value)),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `{}` must be a number, got {}", name, other.type_name()))),
+    }
+}
+
+fn floor(args: &[Object]) -> Result<Object, Object> {
+    rounding("floor", args, f64::floor)
+}
+
+fn ceil(args: &[Object]) -> Result<Object, Object> {
+    rounding("ceil", args, f64::ceil)
+}
+
+fn round(args: &[Object]) -> Result<Object, Object> {
+    rounding("round", args, f64::round)
+}
+
+fn gcd(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let mut a = expect_integer("gcd", &args[0])?.unsigned_abs();
+    let mut b = expect_integer("gcd", &args[1])?.unsigned_abs();
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    i64::try_from(a).map(Object::Integer).map_err(|_| Object::error_of(ErrorKind::Arithmetic, "integer overflow".to_string()))
+}
+
+fn clamp(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 3)?;
+    for arg in args {
+        expect_number("clamp", arg)?;
+    }
+    let (value, low, high) = (&args[0], &args[1], &args[2]);
+    if compare_numbers(low, high) == Some(Ordering::Greater) {
+        return Err(Object::error_of(ErrorKind::Argument, format!("argument to `clamp` must have low <= high, got {} and {}", low, high)));
+    }
+
+    if compare_numbers(value, low) == Some(Ordering::Less) {
+        Ok(low.clone())
+    } else if compare_numbers(value, high) == Some(Ordering::Greater) {
+        Ok(high.clone())
+    } else {
+        Ok(value.clone())
+    }
+}
+
+// Converts a float (truncating towards zero), boolean or numeric string.
+fn int(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    match &args[0] {
+        Object::Integer(value) => Ok(Object::Integer(*value)),
+        Object::Float(value) => float_to_integer("int", value.trunc()),
+        Object::Boolean(value) => Ok(Object::Integer(*value as i64)),
+        Object::String(value) => value.trim().parse::<i64>()
+            .map(Object::Integer)
+            .map_err(|_| Object::error_of(ErrorKind::Argument, format!("could not convert {:?} to INTEGER", value))),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `int` not supported, got {}", other.type_name()))),
+    }
+}
+
+fn float(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    match &args[0] {
+        Object::Integer(value) => Ok(Object::Float(*value as f64)),
+        Object::Float(value) => Ok(Object::Float(*value)),
+        Object::String(value) => value.trim().parse::<f64>()
+            .map(Object::Float)
+            .map_err(|_| Object::error_of(ErrorKind::Argument, format!("could not convert {:?} to FLOAT", value))),
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `float` not supported, got {}", other.type_name()))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builtins::testing::check;
+
+    #[test]
+    fn test_abs(){
+        check(&[
+            ("abs(-5)", "5"),
+            ("abs(5)", "5"),
+            ("abs(-2.5)", "2.5"),
+            ("abs(\"a\")", "ERROR: argument to `abs` must be a number, got STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_min_and_max(){
+        check(&[
+            ("min(3, 1, 2)", "1"),
+            ("max(3, 1, 2)", "3"),
+            ("min([4, 2.5, 3])", "2.5"),
+            ("max([1, 1.0])", "1"),
+            ("max(7)", "7"),
+            ("min([])", "ERROR: argument to `min` must not be an empty array"),
+            ("max()", "ERROR: wrong number of arguments. got=0, want=1 or more"),
+            ("max(1, \"a\")", "ERROR: argument to `max` must be a number, got STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_pow(){
+        check(&[
+            ("pow(2, 10)", "1024"),
+            ("pow(2, -2)", "0.25"),
+            ("pow(9, 0.5)", "3.0"),
+            ("pow(2, 64)", "ERROR: integer overflow"),
+            ("pow(true, 1)", "ERROR: argument to `pow` must be a number, got BOOLEAN"),
+        ]);
+    }
+
+    #[test]
+    fn test_sqrt(){
+        check(&[
+            ("sqrt(16)", "4.0"),
+            ("sqrt(2.25)", "1.5"),
+            ("sqrt(-1)", "ERROR: argument to `sqrt` must not be negative"),
+        ]);
+    }
+
+    #[test]
+    fn test_rounding(){
+        check(&[
+            ("floor(2.7)", "2"),
+            ("floor(-2.5)", "-3"),
+            ("ceil(2.1)", "3"),
+            ("ceil(-2.1)", "-2"),
+            ("round(2.5)", "3"),
+            ("round(-2.5)", "-3"),
+            ("round(2.4)", "2"),
+            ("floor(4)", "4"),
+            ("round(pow(10.0, 300))", "ERROR: result of `round` does not fit in an INTEGER: 1e300"),
+            ("ceil(\"1\")", "ERROR: argument to `ceil` must be a number, got STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_gcd(){
+        check(&[
+            ("gcd(12, 18)", "6"),
+            ("gcd(-12, 18)", "6"),
+            ("gcd(0, 5)", "5"),
+            ("gcd(0, 0)", "0"),
+            ("gcd(1.5, 2)", "ERROR: argument to `gcd` must be INTEGER, got FLOAT"),
+        ]);
+    }
+
+    #[test]
+    fn test_clamp(){
+        check(&[
+            ("clamp(5, 0, 10)", "5"),
+            ("clamp(-5, 0, 10)", "0"),
+            ("clamp(15, 0, 10)", "10"),
+            ("clamp(0.5, 1, 2)", "1"),
+            ("clamp(1, 10, 0)", "ERROR: argument to `clamp` must have low <= high, got 10 and 0"),
+        ]);
+    }
+
+    #[test]
+    fn test_conversions(){
+        check(&[
+            ("int(3.9)", "3"),
+            ("int(-3.9)", "-3"),
+            ("int(\" 42 \")", "42"),
+            ("int(true)", "1"),
+            ("int(\"x\")", "ERROR: could not convert \"x\" to INTEGER"),
+            ("int([])", "ERROR: argument to `int` not supported, got ARRAY"),
+            ("float(2)", "2.0"),
+            ("float(\"2.5\")", "2.5"),
+            ("float(\"x\")", "ERROR: could not convert \"x\" to FLOAT"),
+            ("type(float(1))", "\"FLOAT\""),
+        ]);
+    }
+
+    #[test]
+    fn test_constants(){
+        check(&[
+            ("PI", "3.141592653589793"),
+            ("E", "2.718281828459045"),
+            ("round(PI * 100)", "314"),
+        ]);
+    }
+
+    #[test]
+    fn test_error_kinds(){
+        check(&[
+            ("try { min([]) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { sqrt(-1) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { clamp(1, 10, 0) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { int(\"x\") } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { float(\"x\") } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { int([]) } catch (e) { e.kind }", "\"TypeError\""),
+            ("try { int(pow(10.0, 300)) } catch (e) { e.kind }", "\"ArithmeticError\""),
+        ]);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::object::{BuiltinFunction, ErrorKind, Object};
+
+use super::{expect_arity, expect_arity_range, expect_integer, expect_string};
+
+pub const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("split", builtin!(split)),
+    ("join", builtin!(join)),
+    ("trim", builtin!(trim)),
+    ("upper", builtin!(upper)),
+    ("lower", builtin!(lower)),
+    ("replace", builtin!(replace)),
+    ("contains", builtin!(contains)),
+    ("starts_with", builtin!(starts_with)),
+    ("ends_with", builtin!(ends_with)),
+    ("substr", builtin!(substr)),
+    ("chars", builtin!(chars)),
+    ("format", builtin!(format)),
+];
+
+fn strings(values: Vec<String>) -> Object {
+    Object::new_array(values.into_iter().map(Object::String).collect())
+}
+
+// `split(s)` splits on runs of whitespace, `split(s, sep)` on every `sep`.
+// An empty separator splits into characters.
+fn split(args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 1, 2)?;
+    let value = expect_string("split", &args[0])?;
+    if args.len() == 1 {
+        return Ok(strings(value.split_whitespace().map(String::from).collect()));
+    }
+
+    let separator = expect_string("split", &args[1])?;
+    if separator.is_empty() {
+        return chars(&args[..1]);
+    }
+    Ok(strings(value.split(separator).map(String::from).collect()))
+}
+
+fn join(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let separator = expect_string("join", &args[1])?;
+    match &args[0] {
+        Object::Array(elements) => {
+            Ok(Object::String(elements.borrow().iter().map(|el| el.to_string()).collect::<Vec<String>>().join(separator)))
+        },
+        other => Err(Object::error_of(ErrorKind::Type, format!("argument to `join` must be ARRAY, got {}", other.type_name()))),
+    }
+}
+
+fn trim(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    Ok(Object::String(expect_string("trim", &args[0])?.trim().to_string()))
+}
+
+fn upper(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    Ok(Object::String(expect_string("upper", &args[0])?.to_uppercase()))
+}
+
+fn lower(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    Ok(Object::String(expect_string("lower", &args[0])?.to_lowercase()))
+}
+
+fn replace(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 3)?;
+    let value = expect_string("replace", &args[0])?;
+    let from = expect_string("replace", &args[1])?;
+    let to = expect_string("replace", &args[2])?;
+    if from.is_empty() {
+        return Err(Object::error_of(ErrorKind::Argument, "argument to `replace` must not be an empty pattern".to_string()));
+    }
+    Ok(Object::String(value.replace(from, to)))
+}
+
+fn contains(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let value = expect_string("contains", &args[0])?;
+    Ok(Object::Boolean(value.contains(expect_string("contains", &args[1])?)))
+}
+
+fn starts_with(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let value = expect_string("starts_with", &args[0])?;
+    Ok(Object::Boolean(value.starts_with(expect_string("starts_with", &args[1])?)))
+}
+
+fn ends_with(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 2)?;
+    let value = expect_string("ends_with", &args[0])?;
+    Ok(Object::Boolean(value.ends_with(expect_string("ends_with", &args[1])?)))
+}
+
+// `substr(s, start)` or `substr(s, start, length)`, counted in characters.
+// The length is clamped to the end of the string.
+fn substr(args: &[Object]) -> Result<Object, Object> {
+    expect_arity_range(args, 2, 3)?;
+    let value = expect_string("substr", &args[0])?;
+    let start = expect_integer("substr", &args[1])?;
+    let count = value.chars().count();
+    let start = match usize::try_from(start) {
+        Ok(start) if start <= count => start,
+        _ => return Err(Object::error_of(ErrorKind::Index, format!("index out of range: {} (len {})", start, count))),
+    };
+
+    let length = match args.get(2) {
+        Some(length) => match usize::try_from(expect_integer("substr", length)?) {
+            Ok(length) => length,
+            Err(_) => return Err(Object::error_of(ErrorKind::Argument, "argument to `substr` must not be a negative length".to_string())),
+        },
+        None => count - start,
+    };
+    Ok(Object::String(value.chars().skip(start).take(length).collect()))
+}
+
+fn chars(args: &[Object]) -> Result<Object, Object> {
+    expect_arity(args, 1)?;
+    let value = expect_string("chars", &args[0])?;
+    Ok(strings(value.chars().map(String::from).collect()))
+}
+
+// `format("{} + {}", 1, 2)` substitutes the arguments in order. `{{` and
+// `}}` produce literal braces.
+fn format(args: &[Object]) -> Result<Object, Object> {
+    if args.is_empty() {
+        return Err(Object::wrong_arity(0, "1 or more"));
+    }
+    let template = expect_string("format", &args[0])?;
+    let mut values = args[1..].iter();
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match (ch, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                result.push(ch);
+            },
+            ('{', Some('}')) => {
+                chars.next();
+                match values.next() {
+                    Some(value) => result.push_str(&value.to_string()),
+                    None => return Err(Object::error_of(ErrorKind::Argument, format!("too few arguments to `format`, got {}", args.len() - 1))),
+                }
+            },
+            ('{', _) | ('}', _) => return Err(Object::error_of(ErrorKind::Argument, format!("unmatched `{}` in format string", ch))),
+            _ => result.push(ch),
+        }
+    }
+
+    if values.next().is_some() {
+        return Err(Object::error_of(ErrorKind::Argument, format!("too many arguments to `format`, got {}", args.len() - 1)));
+    }
+    Ok(Object::String(result))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builtins::testing::check;
+
+    #[test]
+    fn test_split(){
+        check(&[
+            (r#"split("a,b,,c", ",")"#, "[a, b, , c]"),
+            (r#"split("  one two\tthree ")"#, "[one, two, three]"),
+            (r#"split("añb", "")"#, "[a, ñ, b]"),
+            (r#"split("a→b→c", "→")"#, "[a, b, c]"),
+            (r#"len(split("", ","))"#, "1"),
+            (r#"split(1, ",")"#, "ERROR: argument to `split` must be STRING, got INTEGER"),
+            (r#"split("a", ",", ",")"#, "ERROR: wrong number of arguments. got=3, want=1-2"),
+        ]);
+    }
+
+    #[test]
+    fn test_join(){
+        check(&[
+            (r#"join(["a", "b", "c"], ", ")"#, r#""a, b, c""#),
+            (r#"join([], "-")"#, r#""""#),
+            (r#"join([1, true, "x"], "")"#, r#""1truex""#),
+            (r#"join(split("a b", " "), "€")"#, r#""a€b""#),
+            (r#"join("abc", ",")"#, "ERROR: argument to `join` must be ARRAY, got STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_trim(){
+        check(&[
+            (r#"trim("  padded \n")"#, r#""padded""#),
+            ("trim(\"\u{3000}wide\u{3000}\")", r#""wide""#),
+            (r#"trim("")"#, r#""""#),
+            ("trim([])", "ERROR: argument to `trim` must be STRING, got ARRAY"),
+        ]);
+    }
+
+    #[test]
+    fn test_upper(){
+        check(&[
+            (r#"upper("hello")"#, r#""HELLO""#),
+            (r#"upper("straße")"#, r#""STRASSE""#),
+            (r#"upper("éa1")"#, r#""ÉA1""#),
+            ("upper(1)", "ERROR: argument to `upper` must be STRING, got INTEGER"),
+        ]);
+    }
+
+    #[test]
+    fn test_lower(){
+        check(&[
+            (r#"lower("HeLLo")"#, r#""hello""#),
+            (r#"lower("ÀÉÎ")"#, r#""àéî""#),
+            ("lower(true)", "ERROR: argument to `lower` must be STRING, got BOOLEAN"),
+        ]);
+    }
+
+    #[test]
+    fn test_replace(){
+        check(&[
+            (r#"replace("a-b-c", "-", "+")"#, r#""a+b+c""#),
+            (r#"replace("héllo", "é", "e")"#, r#""hello""#),
+            (r#"replace("abc", "x", "y")"#, r#""abc""#),
+            (r#"replace("abc", "", "y")"#, "ERROR: argument to `replace` must not be an empty pattern"),
+            (r#"replace("abc", "a")"#, "ERROR: wrong number of arguments. got=2, want=3"),
+        ]);
+    }
+
+    #[test]
+    fn test_contains(){
+        check(&[
+            (r#"contains("hello", "ell")"#, "true"),
+            (r#"contains("hello", "")"#, "true"),
+            (r#"contains("naïve", "ï")"#, "true"),
+            (r#"contains("hello", "xyz")"#, "false"),
+            (r#"contains("hello", 1)"#, "ERROR: argument to `contains` must be STRING, got INTEGER"),
+        ]);
+    }
+
+    #[test]
+    fn test_starts_with(){
+        check(&[
+            (r#"starts_with("monkey", "mon")"#, "true"),
+            (r#"starts_with("monkey", "key")"#, "false"),
+            (r#"starts_with("émoji", "é")"#, "true"),
+            (r#"starts_with([], "a")"#, "ERROR: argument to `starts_with` must be STRING, got ARRAY"),
+        ]);
+    }
+
+    #[test]
+    fn test_ends_with(){
+        check(&[
+            (r#"ends_with("monkey", "key")"#, "true"),
+            (r#"ends_with("monkey", "mon")"#, "false"),
+            (r#"ends_with("café", "é")"#, "true"),
+        ]);
+    }
+
+    #[test]
+    fn test_substr(){
+        check(&[
+            (r#"substr("hello", 1, 3)"#, r#""ell""#),
+            (r#"substr("hello", 2)"#, r#""llo""#),
+            (r#"substr("héllo", 1, 1)"#, r#""é""#),
+            (r#"substr("日本語です", 2, 2)"#, r#""語で""#),
+            (r#"substr("hello", 3, 10)"#, r#""lo""#),
+            (r#"substr("hello", 5)"#, r#""""#),
+            (r#"substr("hello", 6)"#, "ERROR: index out of range: 6 (len 5)"),
+            (r#"substr("hello", -1)"#, "ERROR: index out of range: -1 (len 5)"),
+            (r#"substr("hello", 0, -1)"#, "ERROR: argument to `substr` must not be a negative length"),
+            (r#"substr("hello", "1")"#, "ERROR: argument to `substr` must be INTEGER, got STRING"),
+        ]);
+    }
+
+    #[test]
+    fn test_chars(){
+        check(&[
+            (r#"chars("abc")"#, "[a, b, c]"),
+            (r#"chars("")"#, "[]"),
+            (r#"len(chars("héllo"))"#, "5"),
+            ("chars(1)", "ERROR: argument to `chars` must be STRING, got INTEGER"),
+        ]);
+    }
+
+    #[test]
+    fn test_format(){
+        check(&[
+            (r#"format("{} + {} = {}", 1, 2, 3)"#, r#""1 + 2 = 3""#),
+            (r#"format("no placeholders")"#, r#""no placeholders""#),
+            (r#"format("{}: {}", "list", [1, 2])"#, r#""list: [1, 2]""#),
+            (r#"format("{{}} {}", "é")"#, r#""{} é""#),
+            (r#"format("{} {}", 1)"#, "ERROR: too few arguments to `format`, got 1"),
+            (r#"format("{}", 1, 2)"#, "ERROR: too many arguments to `format`, got 2"),
+            (r#"format("{x}", 1)"#, "ERROR: unmatched `{` in format string"),
+            ("format()", "ERROR: wrong number of arguments. got=0, want=1 or more"),
+        ]);
+    }
+
+    #[test]
+    fn test_string_comparison(){
+        check(&[
+            (r#""apple" < "banana""#, "true"),
+            (r#""apple" > "banana""#, "false"),
+            (r#""abc" < "abd""#, "true"),
+            (r#""ab" < "abc""#, "true"),
+            (r#""b" >= "b""#, "true"),
+            (r#""a" <= "B""#, "false"),
+            (r#""é" > "z""#, "true"),
+            (r#""a" < 1"#, "ERROR: type mismatch: STRING < INTEGER"),
+        ]);
+    }
+
+    #[test]
+    fn test_error_kinds(){
+        check(&[
+            ("try { replace(\"abc\", \"\", \"x\") } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { substr(\"abc\", 0, -1) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { format(\"{}\") } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { format(\"{\") } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { format(\"a\", 1) } catch (e) { e.kind }", "\"ArgumentError\""),
+            ("try { upper(1) } catch (e) { e.kind }", "\"TypeError\""),
+        ]);
+    }
+}
@@ -0,0 +1,107 @@
+#![allow(dead_code)]
+use std::path::{Component, Path, PathBuf};
+
+// Which paths a script may touch through one kind of file access.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Access {
+    #[default]
+    Denied,
+    Any,
+    Paths(Vec<PathBuf>),
+}
+
+impl Access {
+    // Grants access to everything below `root`, or to every path when no
+    // root is given. Granting a root after `Any` keeps `Any`.
+    pub fn grant(&mut self, root: Option<&Path>) {
+        match (root, &mut *self) {
+            (_, Access::Any) => {},
+            (None, _) => *self = Access::Any,
+            (Some(root), Access::Paths(roots)) => roots.push(resolve(root)),
+            (Some(root), Access::Denied) => *self = Access::Paths(vec![resolve(root)]),
+        }
+    }
+
+    pub fn allows(&self, path: &Path) -> bool {
+        match self {
+            Access::Denied => false,
+            Access::Any => true,
+            Access::Paths(roots) => {
+                let path = resolve(path);
+                roots.iter().any(|root| path.starts_with(root))
+            },
+        }
+    }
+}
+
+// What the host lets a script do outside the interpreter. The default grants
+// nothing, so evaluating untrusted code cannot touch the disk or environment.
+// `import` covers loading other scripts and is kept apart from `read` so a
+// program can be split into modules without also reading arbitrary files.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    pub import: Access,
+    pub read: Access,
+    pub write: Access,
+    pub env: bool,
+    pub args: Vec<String>,
+}
+
+// Makes `path` absolute with symlinks and `..` resolved so a grant cannot be
+// escaped through them. A path that does not exist yet is resolved through
+// its parent, which lets `write_file` create new files inside a granted root.
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+        let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        if let Ok(parent) = parent.canonicalize() {
+            return parent.join(name);
+        }
+    }
+    let path = std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => { normalized.pop(); },
+            Component::CurDir => {},
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::Access;
+
+    #[test]
+    fn test_access(){
+        let root = std::env::temp_dir().join(format!("monkey_access_{}", std::process::id()));
+        let data = root.join("data");
+        fs::create_dir_all(&data).unwrap();
+
+        let mut access = Access::default();
+        assert!(!access.allows(&data.join("a.txt")));
+
+        access.grant(Some(&data));
+        assert!(access.allows(&data.join("a.txt")));
+        assert!(access.allows(&data.join("new").join("..").join("b.txt")));
+        assert!(!access.allows(&data.join("..").join("secret.txt")));
+        assert!(!access.allows(&data.join("a").join("b").join("..").join("..").join("..").join("secret.txt")));
+        assert!(!access.allows(&root.join("data2")));
+        assert!(!access.allows(Path::new("/etc/passwd")));
+
+        access.grant(None);
+        assert_eq!(access, Access::Any);
+        access.grant(Some(&data));
+        assert_eq!(access, Access::Any);
+        assert!(access.allows(Path::new("/etc/passwd")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
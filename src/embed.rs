@@ -0,0 +1,362 @@
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::capabilities::Capabilities;
+use crate::evaluator::Evaluator;
+use crate::lexer::Lexer;
+use crate::limits::{LimitExceeded, Limits};
+use crate::object::{Env, Environment, NativeFunction, Object, RuntimeError};
+use crate::parser::Parser;
+use crate::resolver::{Diagnostic, Resolver};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Parse(Vec<String>),
+    Resolve(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    // Evaluation was stopped by one of the configured `Limits`.
+    Limit(LimitExceeded),
+    // A Monkey value could not be converted to the requested Rust type.
+    Conversion(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(errors) => write!(f, "parse error: {}", errors.join("; ")),
+            Error::Resolve(errors) => write!(f, "resolve error: {}", errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join("; ")),
+            Error::Runtime(err) => write!(f, "runtime error: {}", err),
+            Error::Limit(limit) => write!(f, "{}", limit),
+            Error::Conversion(message) => write!(f, "conversion error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// A Monkey session for host applications. Globals persist between calls, so
+// a configuration can be evaluated in pieces and then queried.
+pub struct Interpreter {
+    evaluator: Evaluator,
+    env: Env,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_capabilities(Capabilities::default())
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        Self {
+            evaluator: Evaluator::with_capabilities(capabilities),
+            env: Environment::new(),
+        }
+    }
+
+    // Parses, resolves and evaluates `input` in the global scope, returning
+    // the value of the last statement.
+    pub fn eval_str(&mut self, input: &str) -> Result<Object, Error> {
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        if !p.errors().is_empty() {
+            return Err(Error::Parse(p.errors().clone()));
+        }
+
+        let mut globals = self.evaluator.builtin_names();
+        globals.extend(self.env.borrow().names());
+        let mut r = Resolver::with_globals(&globals);
+        r.declare_constants(&self.env.borrow().constants());
+        r.resolve_program(&program);
+        if !r.errors().is_empty() {
+            return Err(Error::Resolve(r.errors().clone()));
+        }
+
+        into_result(self.evaluator.eval_program(&program, &self.env))
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.evaluator.set_limits(limits);
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl Into<Object>) {
+        self.env.borrow_mut().set(name, value.into());
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.env.borrow().get(name)
+    }
+
+    // Calls a global function, or a builtin, by name.
+    pub fn call_function(&mut self, name: &str, args: Vec<Object>) -> Result<Object, Error> {
+        let function = self.get_global(name)
+            .or_else(|| self.evaluator.builtin(name))
+            .ok_or_else(|| Error::Runtime(RuntimeError::new(format!("identifier not found: {}", name))))?;
+        into_result(self.evaluator.apply_function(function, args))
+    }
+
+    // Makes a Rust closure callable from Monkey as the builtin `name`. An
+    // `Err` returned by the closure becomes a Monkey runtime error.
+    pub fn register_native<F, R, E>(&mut self, name: &str, function: F)
+    where
+        F: Fn(Vec<Object>) -> Result<R, E> + 'static,
+        R: Into<Object>,
+        E: Display,
+    {
+        let native = NativeFunction {
+            name: name.to_string(),
+            function: Box::new(move |args| function(args).map(Into::into).map_err(|err| err.to_string())),
+        };
+        self.evaluator.define_builtin(name, Object::Native(Rc::new(native)));
+    }
+}
+
+fn into_result(value: Object) -> Result<Object, Error> {
+    match value {
+        Object::Error(err) => Err(Error::Runtime(*err)),
+        Object::LimitExceeded(limit) => Err(Error::Limit(limit)),
+        value => Ok(value),
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Integer(value)
+    }
+}
+
+impl From<i32> for Object {
+    fn from(value: i32) -> Self {
+        Object::Integer(value as i64)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        Object::Float(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Boolean(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::String(value.to_string())
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::String(value)
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Self {
+        Object::Null
+    }
+}
+
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Object::Null, Into::into)
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(value: Vec<T>) -> Self {
+        Object::new_array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+fn conversion_error(want: &str, got: &Object) -> Error {
+    Error::Conversion(format!("expected {}, got {}", want, got.type_name()))
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Integer(value) => Ok(value),
+            other => Err(conversion_error("INTEGER", &other)),
+        }
+    }
+}
+
+// Integers are widened, as in Monkey arithmetic.
+impl TryFrom<Object> for f64 {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Float(value) => Ok(value),
+            Object::Integer(value) => Ok(value as f64),
+            other => Err(conversion_error("FLOAT", &other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Boolean(value) => Ok(value),
+            other => Err(conversion_error("BOOLEAN", &other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(value) => Ok(value),
+            other => Err(conversion_error("STRING", &other)),
+        }
+    }
+}
+
+impl<T: TryFrom<Object, Error = Error>> TryFrom<Object> for Vec<T> {
+    type Error = Error;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Array(elements) => elements.borrow().iter().cloned().map(T::try_from).collect(),
+            other => Err(conversion_error("ARRAY", &other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::{Error, Interpreter};
+    use crate::limits::{LimitExceeded, Limits};
+    use crate::object::Object;
+
+    #[test]
+    fn test_eval_str_keeps_globals(){
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let port = 8000 + 80;").unwrap();
+        interpreter.eval_str("let hosts = [\"a\", \"b\"];").unwrap();
+
+        assert_eq!(interpreter.eval_str("port + 1"), Ok(Object::Integer(8081)));
+        assert_eq!(interpreter.get_global("port").map(i64::try_from), Some(Ok(8080)));
+        let hosts: Vec<String> = interpreter.get_global("hosts").unwrap().try_into().unwrap();
+        assert_eq!(hosts, vec!["a", "b"]);
+        assert_eq!(interpreter.get_global("missing"), None);
+    }
+
+    fn runtime_error(result: Result<Object, Error>) -> String {
+        match result {
+            Err(Error::Runtime(err)) => err.message,
+            other => panic!("expected a runtime error. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_str_errors(){
+        let mut interpreter = Interpreter::new();
+        assert!(matches!(interpreter.eval_str("let = 1;"), Err(Error::Parse(_))));
+        match interpreter.eval_str("undefined_name") {
+            Err(Error::Resolve(errors)) => assert_eq!(errors[0].message, "undefined variable undefined_name"),
+            other => panic!("expected a resolve error. got {:?}", other),
+        }
+        assert_eq!(runtime_error(interpreter.eval_str("1 / 0")), "division by zero");
+        assert_eq!(interpreter.eval_str("a; b").unwrap_err().to_string(), "resolve error: undefined variable a at 1:1; undefined variable b at 1:4");
+
+        interpreter.eval_str("const limit = 1;").unwrap();
+        match interpreter.eval_str("limit = 2; limit") {
+            Err(Error::Resolve(errors)) => assert_eq!(errors[0].message, "cannot assign to constant limit"),
+            other => panic!("expected a resolve error. got {:?}", other),
+        }
+        assert_eq!(interpreter.eval_str("limit"), Ok(Object::Integer(1)));
+    }
+
+    #[test]
+    fn test_set_global_and_call_function(){
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("scale", 3);
+        interpreter.set_global("names", vec!["x", "y"]);
+        interpreter.eval_str("let apply = fn(x) { x * scale }; let count = fn() { len(names) };").unwrap();
+
+        assert_eq!(interpreter.call_function("apply", vec![Object::from(5)]), Ok(Object::Integer(15)));
+        assert_eq!(interpreter.call_function("count", vec![]), Ok(Object::Integer(2)));
+        assert_eq!(interpreter.call_function("len", vec!["four".into()]), Ok(Object::Integer(4)));
+        assert_eq!(runtime_error(interpreter.call_function("nope", vec![])), "identifier not found: nope");
+        assert_eq!(runtime_error(interpreter.call_function("apply", vec![])), "wrong number of arguments. got=0, want=1");
+    }
+
+    #[test]
+    fn test_register_native(){
+        let mut interpreter = Interpreter::new();
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        interpreter.register_native("double", move |args: Vec<Object>| {
+            counter.set(counter.get() + 1);
+            let [value] = <[Object; 1]>::try_from(args).map_err(|args| format!("double takes 1 argument, got {}", args.len()))?;
+            let value = i64::try_from(value).map_err(|err| err.to_string())?;
+            Ok::<_, String>(value * 2)
+        });
+
+        assert_eq!(interpreter.eval_str("map([1, 2], double)").unwrap().to_string(), "[2, 4]");
+        assert_eq!(interpreter.eval_str("double(21)"), Ok(Object::Integer(42)));
+        assert_eq!(interpreter.eval_str("type(double)"), Ok(Object::String("BUILTIN".to_string())));
+        assert_eq!(runtime_error(interpreter.eval_str("double(\"a\")")), "conversion error: expected INTEGER, got STRING");
+        assert_eq!(runtime_error(interpreter.eval_str("double()")), "double takes 1 argument, got 0");
+        assert_eq!(calls.get(), 5);
+    }
+
+    #[test]
+    fn test_limits(){
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits {
+            max_steps: Some(10_000),
+            max_call_depth: 50,
+            max_allocation: Some(1_000),
+            timeout: Some(Duration::from_secs(10)),
+            ..Limits::default()
+        });
+        interpreter.eval_str("let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };").unwrap();
+
+        assert_eq!(interpreter.eval_str("f(49)"), Ok(Object::Integer(49)));
+        assert_eq!(interpreter.eval_str("f(50)"), Err(Error::Limit(LimitExceeded::CallDepth(50))));
+        assert_eq!(interpreter.call_function("f", vec![Object::from(100)]), Err(Error::Limit(LimitExceeded::CallDepth(50))));
+        assert_eq!(interpreter.eval_str("while (true) {}"), Err(Error::Limit(LimitExceeded::Steps(10_000))));
+        assert_eq!(interpreter.eval_str("range(2000)"), Err(Error::Limit(LimitExceeded::Allocation(1_000))));
+        assert_eq!(interpreter.eval_str("let s = \"ab\"; while (true) { s += s; }"), Err(Error::Limit(LimitExceeded::Allocation(1_000))));
+        assert_eq!(interpreter.eval_str("len(range(900))"), Ok(Object::Integer(900)), "budgets reset between calls");
+
+        interpreter.set_limits(Limits { timeout: Some(Duration::from_millis(20)), ..Limits::default() });
+        assert_eq!(interpreter.eval_str("while (true) {}"), Err(Error::Limit(LimitExceeded::Timeout(Duration::from_millis(20)))));
+    }
+
+    #[test]
+    fn test_conversions(){
+        assert_eq!(Object::from(1.5), Object::Float(1.5));
+        assert_eq!(Object::from(Some(true)), Object::Boolean(true));
+        assert_eq!(Object::from(None::<i64>), Object::Null);
+        assert_eq!(f64::try_from(Object::Integer(2)), Ok(2.0));
+        assert_eq!(bool::try_from(Object::Null), Err(Error::Conversion("expected BOOLEAN, got NULL".to_string())));
+        assert_eq!(
+            Vec::<i64>::try_from(Object::from(vec![Object::Integer(1), Object::from("x")])),
+            Err(Error::Conversion("expected INTEGER, got STRING".to_string()))
+        );
+    }
+}
@@ -0,0 +1,1292 @@
+#![allow(dead_code)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::ast::{ArrayLiteral, AssignExpression, BlockStatement, Boolean, CallExpression, ErrorExpression, Expression, FloatLiteral, FunctionLiteral, Identifier, IfExpression, ImportExpression, IndexExpression, InfixExpression, IntegerLiteral, MemberExpression, MissingExpression, PrefixExpression, Program, Statement, StringLiteral, TryExpression};
+use crate::builtins;
+use crate::capabilities::Capabilities;
+use crate::lexer::Lexer;
+use crate::limits::{LimitExceeded, Limits};
+use crate::object::{BindError, Env, Environment, ErrorKind, Frame, Object, RuntimeError};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::token::Token;
+
+// Hosts only run programs without parse errors; the error nodes the parser
+// leaves in a broken program evaluate to this.
+const UNPARSED: &str = "cannot evaluate code that did not parse";
+
+pub struct Evaluator {
+    builtins: HashMap<String, Object>,
+    capabilities: Capabilities,
+    // Evaluated modules by canonical path, so each file runs once.
+    modules: HashMap<PathBuf, Object>,
+    // Files being evaluated, outermost first. Imports are resolved relative
+    // to the last one and a path already on the stack is an import cycle.
+    loading: Vec<PathBuf>,
+    limits: Limits,
+    usage: Usage,
+    // Nesting of public entry points; the outermost one resets `usage`.
+    entered: usize,
+    // Where `puts` writes, stdout unless the host captures it.
+    output: Box<dyn Write>,
+}
+
+// The writer behind `capture_output`.
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Usage {
+    steps: u64,
+    call_depth: usize,
+    allocated: usize,
+    started: Instant,
+    // Where the Rust stack stood when evaluation started.
+    stack_start: usize,
+}
+
+impl Usage {
+    fn new() -> Self {
+        Self { steps: 0, call_depth: 0, allocated: 0, started: Instant::now(), stack_start: stack_position() }
+    }
+}
+
+// The address of a local variable, which moves down as the Rust stack grows.
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::with_capabilities(Capabilities::default())
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        Self {
+            builtins: builtins::registry(),
+            capabilities,
+            modules: HashMap::new(),
+            loading: vec![],
+            limits: Limits::default(),
+            usage: Usage::new(),
+            entered: 0,
+            output: Box::new(io::stdout()),
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        self.output.as_mut()
+    }
+
+    // Sends what `puts` prints to a buffer instead, which the host can read
+    // and clear between evaluations.
+    pub fn capture_output(&mut self) -> Rc<RefCell<Vec<u8>>> {
+        let buffer = Rc::new(RefCell::new(vec![]));
+        self.output = Box::new(Capture(Rc::clone(&buffer)));
+        buffer
+    }
+
+    pub fn builtin_names(&self) -> Vec<String> {
+        self.builtins.keys().cloned().collect()
+    }
+
+    // Adds or replaces a builtin, which every program sees like `len`.
+    pub fn define_builtin(&mut self, name: &str, value: Object) {
+        self.builtins.insert(name.to_string(), value);
+    }
+
+    pub fn builtin(&self, name: &str) -> Option<Object> {
+        self.builtins.get(name).cloned()
+    }
+
+    pub fn eval_program(&mut self, program: &Program, env: &Env) -> Object {
+        self.enter();
+        let mut result = Object::Null;
+        for stmt in program.statements.iter() {
+            result = self.eval_statement(stmt, env);
+            match result {
+                Object::ReturnValue(value) => {
+                    result = *value;
+                    break;
+                },
+                Object::Error(_) | Object::LimitExceeded(_) => break,
+                _ => {}
+            }
+        }
+        self.exit();
+        result
+    }
+
+    fn enter(&mut self) {
+        if self.entered == 0 {
+            self.usage = Usage::new();
+        }
+        self.entered += 1;
+    }
+
+    fn exit(&mut self) {
+        self.entered -= 1;
+    }
+
+    // Counts one evaluation step, checking the step, time and stack budgets.
+    fn step(&mut self) -> Option<Object> {
+        if self.usage.stack_start.saturating_sub(stack_position()) > self.limits.max_stack {
+            return Some(Object::LimitExceeded(LimitExceeded::Stack(self.limits.max_stack)));
+        }
+        self.charge(1).err()
+    }
+
+    // Counts `count` steps of work done inside a builtin, such as each
+    // element it builds or visits, checking the step and time budgets.
+    pub fn charge(&mut self, count: u64) -> Result<(), Object> {
+        let before = self.usage.steps;
+        self.usage.steps = before.saturating_add(count);
+        if let Some(max) = self.limits.max_steps {
+            if self.usage.steps > max {
+                return Err(Object::LimitExceeded(LimitExceeded::Steps(max)));
+            }
+        }
+        if let Some(timeout) = self.limits.timeout {
+            // The clock is read once every 256 steps.
+            if self.usage.steps / 256 != before / 256 && self.usage.started.elapsed() > timeout {
+                return Err(Object::LimitExceeded(LimitExceeded::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
+
+    // Fails when `size` more elements would exceed the allocation budget,
+    // without charging them. Builtins use it before building large results.
+    pub fn check_allocation(&self, size: usize) -> Result<(), Object> {
+        match self.limits.max_allocation {
+            Some(max) if self.usage.allocated.saturating_add(size) > max => Err(Object::LimitExceeded(LimitExceeded::Allocation(max))),
+            _ => Ok(()),
+        }
+    }
+
+    // Charges a newly built array or string against the allocation budget,
+    // returning it unchanged or the limit error.
+    fn allocate(&mut self, value: Object) -> Object {
+        let size = match &value {
+            Object::Array(elements) => elements.borrow().len(),
+            Object::String(value) => value.len(),
+            _ => return value,
+        };
+        if let Err(err) = self.check_allocation(size) {
+            return err;
+        }
+        self.usage.allocated += size;
+        value
+    }
+
+    // Evaluates a program read from `path`, so its imports are resolved
+    // relative to that file.
+    pub fn eval_file_program(&mut self, program: &Program, env: &Env, path: &Path) -> Object {
+        self.loading.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        let result = self.eval_program(program, env);
+        self.loading.pop();
+        result
+    }
+
+    fn eval_block_statement(&mut self, block: &BlockStatement, env: &Env) -> Object {
+        let mut result = Object::Null;
+        for stmt in block.statements.iter() {
+            result = self.eval_statement(stmt, env);
+            if matches!(result, Object::ReturnValue(_) | Object::Error(_) | Object::LimitExceeded(_) | Object::Break | Object::Continue) {
+                return result;
+            }
+        }
+        result
+    }
+
+    fn eval_statement(&mut self, stmt: &Statement, env: &Env) -> Object {
+        if let Some(err) = self.step() {
+            return err;
+        }
+        match stmt {
+            Statement::Let { token, name, value } => {
+                let value = self.eval_expression(value.as_ref(), env);
+                if value.is_error() {
+                    return value;
+                }
+                // The parser rejects this within one program; it can still
+                // happen across the inputs of a session.
+                if env.borrow_mut().declare(&name.value, value, *token == Token::Const).is_err() {
+                    return Object::error_of(ErrorKind::Type, format!("cannot redeclare constant {}", name.value));
+                }
+                Object::Null
+            },
+            Statement::Return { token: _, value } => {
+                let value = self.eval_expression(value.as_ref(), env);
+                if value.is_error() {
+                    return value;
+                }
+                Object::ReturnValue(Box::new(value))
+            },
+            Statement::Expression { token: _, expression } => self.eval_expression(expression.as_ref(), env),
+            Statement::While { token: _, condition, body } => self.eval_while_statement(condition.as_ref(), body, env),
+            Statement::For { token: _, variable, iterable, body } => self.eval_for_statement(variable, iterable.as_ref(), body, env),
+            Statement::Break { .. } => Object::Break,
+            Statement::Continue { .. } => Object::Continue,
+            Statement::Throw { token: _, value, span } => {
+                let value = self.eval_expression(value.as_ref(), env);
+                if value.is_error() {
+                    return value;
+                }
+                // Rethrowing a caught error keeps where it was first raised.
+                let err = match value {
+                    Object::Caught(err) => (*err).clone(),
+                    Object::String(message) => RuntimeError { span: Some(*span), ..RuntimeError::new(message) },
+                    other => RuntimeError { span: Some(*span), ..RuntimeError::new(other.to_string()) },
+                };
+                Object::Error(Box::new(err))
+            },
+            Statement::Error { span, .. } => {
+                Object::Error(Box::new(RuntimeError { span: Some(*span), ..RuntimeError::new(UNPARSED) }))
+            },
+        }
+    }
+
+    fn eval_while_statement(&mut self, condition: &dyn Expression, body: &BlockStatement, env: &Env) -> Object {
+        loop {
+            let condition = self.eval_expression(condition, env);
+            if condition.is_error() {
+                return condition;
+            }
+            if !condition.is_truthy() {
+                return Object::Null;
+            }
+
+            // Like a function call, every iteration gets its own scope.
+            let body_env = Environment::new_enclosed(env);
+            match self.eval_block_statement(body, &body_env) {
+                Object::Break => return Object::Null,
+                result @ (Object::ReturnValue(_) | Object::Error(_) | Object::LimitExceeded(_)) => return result,
+                _ => {}
+            }
+        }
+    }
+
+    fn eval_for_statement(&mut self, variable: &Identifier, iterable: &dyn Expression, body: &BlockStatement, env: &Env) -> Object {
+        let items = match self.eval_expression(iterable, env) {
+            Object::Array(elements) => elements.borrow().to_vec(),
+            Object::String(value) => value.chars().map(|ch| Object::String(ch.to_string())).collect(),
+            err @ (Object::Error(_) | Object::LimitExceeded(_)) => return err,
+            other => return Object::error_of(ErrorKind::Type, format!("cannot iterate over {}", other.type_name())),
+        };
+
+        for item in items {
+            let body_env = Environment::new_enclosed(env);
+            body_env.borrow_mut().set(&variable.value, item);
+            match self.eval_block_statement(body, &body_env) {
+                Object::Break => break,
+                result @ (Object::ReturnValue(_) | Object::Error(_) | Object::LimitExceeded(_)) => return result,
+                _ => {}
+            }
+        }
+        Object::Null
+    }
+
+    pub fn eval_expression(&mut self, exp: &dyn Expression, env: &Env) -> Object {
+        if let Some(err) = self.step() {
+            return err;
+        }
+        let mut result = self.eval_expression_node(exp, env);
+        // Errors are reported at the innermost expression that has a span.
+        if let Object::Error(err) = &mut result {
+            if err.span.is_none() {
+                err.span = exp.span();
+            }
+        }
+        result
+    }
+
+    fn eval_expression_node(&mut self, exp: &dyn Expression, env: &Env) -> Object {
+        let exp = exp.as_any();
+        if let Some(integer) = exp.downcast_ref::<IntegerLiteral>() {
+            Object::Integer(integer.value)
+        } else if let Some(float) = exp.downcast_ref::<FloatLiteral>() {
+            Object::Float(float.value)
+        } else if let Some(boolean) = exp.downcast_ref::<Boolean>() {
+            Object::Boolean(boolean.value)
+        } else if let Some(string) = exp.downcast_ref::<StringLiteral>() {
+            Object::String(string.value.clone())
+        } else if let Some(ident) = exp.downcast_ref::<Identifier>() {
+            self.eval_identifier(ident, env)
+        } else if let Some(prefix) = exp.downcast_ref::<PrefixExpression>() {
+            let right = self.eval_expression(prefix.right.as_ref(), env);
+            if right.is_error() {
+                return right;
+            }
+            eval_prefix_expression(&prefix.operator, right)
+        } else if let Some(infix) = exp.downcast_ref::<InfixExpression>() {
+            self.eval_infix_expression(infix, env)
+        } else if let Some(assign) = exp.downcast_ref::<AssignExpression>() {
+            self.eval_assign_expression(assign, env)
+        } else if let Some(if_exp) = exp.downcast_ref::<IfExpression>() {
+            self.eval_if_expression(if_exp, env)
+        } else if let Some(try_exp) = exp.downcast_ref::<TryExpression>() {
+            self.eval_try_expression(try_exp, env)
+        } else if let Some(function) = exp.downcast_ref::<FunctionLiteral>() {
+            Object::Function {
+                name: function.name.clone(),
+                parameters: function.parameters.clone(),
+                body: Rc::clone(&function.body),
+                env: Rc::clone(env),
+            }
+        } else if let Some(call) = exp.downcast_ref::<CallExpression>() {
+            self.eval_call_expression(call, env)
+        } else if let Some(array) = exp.downcast_ref::<ArrayLiteral>() {
+            match self.eval_expressions(&array.elements, env) {
+                Ok(elements) => self.allocate(Object::new_array(elements)),
+                Err(err) => err,
+            }
+        } else if let Some(index) = exp.downcast_ref::<IndexExpression>() {
+            let left = self.eval_expression(index.left.as_ref(), env);
+            if left.is_error() {
+                return left;
+            }
+            let index = self.eval_expression(index.index.as_ref(), env);
+            if index.is_error() {
+                return index;
+            }
+            eval_index_expression(left, index)
+        } else if let Some(member) = exp.downcast_ref::<MemberExpression>() {
+            self.eval_member_expression(member, env)
+        } else if let Some(import) = exp.downcast_ref::<ImportExpression>() {
+            self.eval_import_expression(import)
+        } else if exp.is::<MissingExpression>() || exp.is::<ErrorExpression>() {
+            Object::error(UNPARSED)
+        } else {
+            Object::error("unknown expression".to_string())
+        }
+    }
+
+    fn eval_if_expression(&mut self, if_exp: &IfExpression, env: &Env) -> Object {
+        let condition = self.eval_expression(if_exp.condition.as_ref(), env);
+        if condition.is_error() {
+            return condition;
+        }
+        if condition.is_truthy() {
+            self.eval_block_statement(&if_exp.consequence, env)
+        } else if let Some(alt) = &if_exp.alternative {
+            self.eval_block_statement(alt, env)
+        } else {
+            Object::Null
+        }
+    }
+
+    // Only runtime errors are caught: limits still abort the evaluation, and
+    // `return`, `break` and `continue` pass through after `finally` runs.
+    fn eval_try_expression(&mut self, try_exp: &TryExpression, env: &Env) -> Object {
+        let mut result = self.eval_block_statement(&try_exp.block, env);
+        if let (Object::Error(err), Some(catch)) = (&result, &try_exp.catch) {
+            // The caught error is only bound inside the catch block.
+            let catch_env = Environment::new_enclosed(env);
+            catch_env.borrow_mut().set(&catch.parameter.value, Object::Caught(Rc::new((**err).clone())));
+            result = self.eval_block_statement(&catch.body, &catch_env);
+        }
+        if matches!(result, Object::LimitExceeded(_)) {
+            return result;
+        }
+
+        if let Some(finally) = &try_exp.finally {
+            // The value of `finally` is discarded unless it leaves the block.
+            let finished = self.eval_block_statement(finally, env);
+            if matches!(finished, Object::ReturnValue(_) | Object::Error(_) | Object::LimitExceeded(_) | Object::Break | Object::Continue) {
+                return finished;
+            }
+        }
+        result
+    }
+
+    fn eval_call_expression(&mut self, call: &CallExpression, env: &Env) -> Object {
+        let function = self.eval_expression(call.function.as_ref(), env);
+        if function.is_error() {
+            return function;
+        }
+        let args = match self.eval_expressions(&call.arguments, env) {
+            Ok(args) => args,
+            Err(err) => return err,
+        };
+        let name = match &function {
+            Object::Function { name, .. } => name.clone().unwrap_or_else(|| "<anonymous>".to_string()),
+            Object::Builtin { name, .. } => name.to_string(),
+            Object::Native(native) => native.name.clone(),
+            _ => String::new(),
+        };
+        let mut result = self.apply_function(function, args);
+        // An error that already has a span was raised inside the callee, so
+        // this call becomes a frame of its trace. Otherwise the call itself
+        // failed and the error is reported at it.
+        if let Object::Error(err) = &mut result {
+            if err.span.is_some() {
+                err.frames.push(Frame { function: name, call_site: call.span });
+            }
+        }
+        result
+    }
+
+    fn eval_member_expression(&mut self, member: &MemberExpression, env: &Env) -> Object {
+        match self.eval_expression(member.left.as_ref(), env) {
+            Object::Module { path, exports } => match exports.get(&member.property.value) {
+                Some(value) => value.clone(),
+                None => Object::error_of(ErrorKind::Name, format!("module {:?} has no member {}", path, member.property.value)),
+            },
+            Object::Caught(err) => match member.property.value.as_str() {
+                "message" => Object::String(err.message.clone()),
+                "kind" => Object::String(err.kind.to_string()),
+                "span" => err.span.map_or(Object::Null, |span| Object::String(span.to_string())),
+                name => Object::error_of(ErrorKind::Name, format!("error has no member {}", name)),
+            },
+            err @ (Object::Error(_) | Object::LimitExceeded(_)) => err,
+            other => Object::error_of(ErrorKind::Type, format!("cannot access member {} of {}", member.property.value, other.type_name())),
+        }
+    }
+
+    fn eval_import_expression(&mut self, import: &ImportExpression) -> Object {
+        let base = self.loading.last().and_then(|file| file.parent()).unwrap_or(Path::new("."));
+        let path = base.join(&import.path);
+        if !self.capabilities.import.allows(&path) {
+            return Object::error_of(ErrorKind::Permission, format!("permission denied: `import` needs import access to {:?}", import.path));
+        }
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(err) => return Object::error_of(ErrorKind::Import, format!("could not import {:?}: {}", import.path, err)),
+        };
+        if let Some(module) = self.modules.get(&path) {
+            return module.clone();
+        }
+        if let Some(start) = self.loading.iter().position(|file| *file == path) {
+            let chain = self.loading[start..].iter().chain([&path]).map(|file| display_path(file)).collect::<Vec<String>>();
+            return Object::error_of(ErrorKind::Import, format!("import cycle: {}", chain.join(" -> ")));
+        }
+
+        let name = display_path(&path);
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(err) => return Object::error_of(ErrorKind::Import, format!("could not import {:?}: {}", import.path, err)),
+        };
+        let mut l = Lexer::new(&input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        if !p.errors().is_empty() {
+            return Object::error_of(ErrorKind::Import, format!("could not parse module {:?}: {}", name, p.errors().join("; ")));
+        }
+        let mut r = Resolver::with_globals(&self.builtin_names());
+        r.resolve_program(&program);
+        if !r.errors().is_empty() {
+            let errors = r.errors().iter().map(|e| e.to_string()).collect::<Vec<String>>();
+            return Object::error_of(ErrorKind::Import, format!("could not resolve module {:?}: {}", name, errors.join("; ")));
+        }
+
+        let env = Environment::new();
+        let result = self.eval_file_program(&program, &env, &path);
+        if result.is_error() {
+            return result;
+        }
+
+        let mut exports = HashMap::new();
+        for stmt in program.statements.iter() {
+            if let Statement::Let { name, .. } = stmt {
+                if let Some(value) = env.borrow().get(&name.value) {
+                    exports.insert(name.value.clone(), value);
+                }
+            }
+        }
+        let module = Object::Module { path: name, exports: Rc::new(exports) };
+        self.modules.insert(path, module.clone());
+        module
+    }
+
+    fn eval_expressions(&mut self, exps: &[Box<dyn Expression>], env: &Env) -> Result<Vec<Object>, Object> {
+        let mut result = vec![];
+        for exp in exps {
+            let evaluated = self.eval_expression(exp.as_ref(), env);
+            if evaluated.is_error() {
+                return Err(evaluated);
+            }
+            result.push(evaluated);
+        }
+        Ok(result)
+    }
+
+    // Names are looked up in the environment first, so a `let` can shadow a
+    // builtin.
+    fn eval_identifier(&self, ident: &Identifier, env: &Env) -> Object {
+        if let Some(value) = env.borrow().get(&ident.value) {
+            return value;
+        }
+        if let Some(builtin) = self.builtins.get(&ident.value) {
+            return builtin.clone();
+        }
+        Object::error_of(ErrorKind::Name, format!("identifier not found: {}", ident.value))
+    }
+
+    fn eval_infix_expression(&mut self, infix: &InfixExpression, env: &Env) -> Object {
+        let left = self.eval_expression(infix.left.as_ref(), env);
+        if left.is_error() {
+            return left;
+        }
+
+        // `&&` and `||` only evaluate the right side when it decides the result.
+        match infix.operator.as_str() {
+            "&&" if !left.is_truthy() => return Object::Boolean(false),
+            "||" if left.is_truthy() => return Object::Boolean(true),
+            _ => {}
+        }
+
+        let right = self.eval_expression(infix.right.as_ref(), env);
+        if right.is_error() {
+            return right;
+        }
+
+        match infix.operator.as_str() {
+            "&&" | "||" => Object::Boolean(right.is_truthy()),
+            operator => {
+                let result = eval_binary_operation(operator, left, right);
+                self.allocate(result)
+            },
+        }
+    }
+
+    fn eval_assign_expression(&mut self, assign: &AssignExpression, env: &Env) -> Object {
+        let value = self.eval_expression(assign.value.as_ref(), env);
+        if value.is_error() {
+            return value;
+        }
+
+        let target = assign.target.as_any();
+        if let Some(ident) = target.downcast_ref::<Identifier>() {
+            let value = match compound_operator(&assign.operator) {
+                Some(operator) => {
+                    let current = self.eval_identifier(ident, env);
+                    if current.is_error() {
+                        return current;
+                    }
+                    let result = eval_binary_operation(operator, current, value);
+                    self.allocate(result)
+                },
+                None => value,
+            };
+            if value.is_error() {
+                return value;
+            }
+            match env.borrow_mut().assign(&ident.value, value.clone()) {
+                Ok(()) => {},
+                Err(BindError::Unbound) => return Object::error_of(ErrorKind::Name, format!("identifier not found: {}", ident.value)),
+                Err(BindError::Constant) => return Object::error_of(ErrorKind::Type, format!("cannot assign to constant {}", ident.value)),
+            }
+            value
+        } else if let Some(index_exp) = target.downcast_ref::<IndexExpression>() {
+            let left = self.eval_expression(index_exp.left.as_ref(), env);
+            if left.is_error() {
+                return left;
+            }
+            let index = self.eval_expression(index_exp.index.as_ref(), env);
+            if index.is_error() {
+                return index;
+            }
+            let value = match compound_operator(&assign.operator) {
+                Some(operator) => {
+                    let current = eval_index_expression(left.clone(), index.clone());
+                    if current.is_error() {
+                        return current;
+                    }
+                    let result = eval_binary_operation(operator, current, value);
+                    self.allocate(result)
+                },
+                None => value,
+            };
+            if value.is_error() {
+                return value;
+            }
+            match (left, index) {
+                (Object::Array(elements), Object::Integer(_)) if value.contains_array(&elements) => {
+                    Object::error_of(ErrorKind::Argument, "cannot store an array inside itself".to_string())
+                },
+                (Object::Array(elements), Object::Integer(i)) => {
+                    let mut elements = elements.borrow_mut();
+                    let len = elements.len();
+                    match usize::try_from(i).ok().and_then(|i| elements.get_mut(i)) {
+                        Some(slot) => {
+                            *slot = value.clone();
+                            value
+                        },
+                        None => Object::error_of(ErrorKind::Index, format!("index out of range: {} (len {})", i, len)),
+                    }
+                },
+                (left, index) => Object::error_of(ErrorKind::Type, format!("index assignment not supported: {}[{}]", left.type_name(), index.type_name())),
+            }
+        } else {
+            Object::error_of(ErrorKind::Type, format!("invalid assignment target: {}", assign.target))
+        }
+    }
+
+    pub fn apply_function(&mut self, function: Object, args: Vec<Object>) -> Object {
+        self.enter();
+        let result = match function {
+            Object::Function { name: _, parameters, body, env } => {
+                if parameters.len() != args.len() {
+                    self.exit();
+                    return Object::wrong_arity(args.len(), parameters.len());
+                }
+                if self.usage.call_depth >= self.limits.max_call_depth {
+                    self.exit();
+                    return Object::LimitExceeded(LimitExceeded::CallDepth(self.limits.max_call_depth));
+                }
+                let function_env = Environment::new_enclosed(&env);
+                for (param, arg) in parameters.iter().zip(args) {
+                    function_env.borrow_mut().set(&param.value, arg);
+                }
+                self.usage.call_depth += 1;
+                let result = self.eval_block_statement(&body, &function_env);
+                self.usage.call_depth -= 1;
+                match result {
+                    Object::ReturnValue(value) => *value,
+                    result => result,
+                }
+            },
+            Object::Builtin { name: _, function } => {
+                let result = function(self, args);
+                self.allocate(result)
+            },
+            Object::Native(native) => {
+                let result = (native.function)(args).unwrap_or_else(Object::error);
+                self.allocate(result)
+            },
+            other => Object::error_of(ErrorKind::Type, format!("not a function: {}", other.type_name())),
+        };
+        self.exit();
+        result
+    }
+}
+
+fn compound_operator(operator: &str) -> Option<&'static str> {
+    match operator {
+        "+=" => Some("+"),
+        "-=" => Some("-"),
+        "*=" => Some("*"),
+        "/=" => Some("/"),
+        _ => None,
+    }
+}
+
+fn eval_prefix_expression(operator: &str, right: Object) -> Object {
+    match (operator, right) {
+        ("!", right) => Object::Boolean(!right.is_truthy()),
+        ("-", Object::Integer(value)) => match value.checked_neg() {
+            Some(value) => Object::Integer(value),
+            None => Object::error_of(ErrorKind::Arithmetic, "integer overflow".to_string()),
+        },
+        ("-", Object::Float(value)) => Object::Float(-value),
+        ("~", Object::Integer(value)) => Object::Integer(!value),
+        (operator, right) => Object::error_of(ErrorKind::Type, format!("unknown operator: {}{}", operator, right.type_name())),
+    }
+}
+
+// Integers stay integers; as soon as one operand is a float both are
+// promoted and the result is a float (comparisons give booleans).
+pub fn eval_binary_operation(operator: &str, left: Object, right: Object) -> Object {
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => eval_integer_infix_expression(operator, left, right),
+        (Object::Float(left), Object::Float(right)) => eval_float_infix_expression(operator, left, right),
+        (Object::Integer(left), Object::Float(right)) => eval_float_infix_expression(operator, left as f64, right),
+        (Object::Float(left), Object::Integer(right)) => eval_float_infix_expression(operator, left, right as f64),
+        (Object::String(left), Object::String(right)) => eval_string_infix_expression(operator, left, right),
+        (Object::Boolean(left), Object::Boolean(right)) => match operator {
+            "==" => Object::Boolean(left == right),
+            "!=" => Object::Boolean(left != right),
+            _ => Object::error_of(ErrorKind::Type, format!("unknown operator: BOOLEAN {} BOOLEAN", operator)),
+        },
+        (left, right) if left.type_name() != right.type_name() => {
+            Object::error_of(ErrorKind::Type, format!("type mismatch: {} {} {}", left.type_name(), operator, right.type_name()))
+        },
+        (left, right) => match operator {
+            "==" => Object::Boolean(left == right),
+            "!=" => Object::Boolean(left != right),
+            _ => Object::error_of(ErrorKind::Type, format!("unknown operator: {} {} {}", left.type_name(), operator, right.type_name())),
+        },
+    }
+}
+
+// Shows `path` relative to the working directory when it is below it.
+fn display_path(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd).unwrap_or(path).display().to_string()
+}
+
+fn eval_integer_infix_expression(operator: &str, left: i64, right: i64) -> Object {
+    let result = match operator {
+        "<" => return Object::Boolean(left < right),
+        ">" => return Object::Boolean(left > right),
+        "<=" => return Object::Boolean(left <= right),
+        ">=" => return Object::Boolean(left >= right),
+        "==" => return Object::Boolean(left == right),
+        "!=" => return Object::Boolean(left != right),
+        "&" => Some(left & right),
+        "|" => Some(left | right),
+        "^" => Some(left ^ right),
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return Object::error_of(ErrorKind::Arithmetic, "division by zero".to_string()),
+        "/" => left.checked_div(right),
+        "%" => left.checked_rem(right),
+        // A negative exponent has no integer result.
+        "**" if right < 0 => return eval_float_infix_expression(operator, left as f64, right as f64),
+        "<<" | ">>" if right < 0 => {
+            return Object::error_of(ErrorKind::Arithmetic, format!("negative right operand for {}: {}", operator, right))
+        },
+        "<<" => u32::try_from(right).ok().and_then(|right| left.checked_shl(right)),
+        ">>" => u32::try_from(right).ok().and_then(|right| left.checked_shr(right)),
+        "**" => u32::try_from(right).ok().and_then(|right| left.checked_pow(right)),
+        _ => return Object::error_of(ErrorKind::Type, format!("unknown operator: INTEGER {} INTEGER", operator)),
+    };
+
+    match result {
+        Some(value) => Object::Integer(value),
+        None => Object::error_of(ErrorKind::Arithmetic, "integer overflow".to_string()),
+    }
+}
+
+fn eval_float_infix_expression(operator: &str, left: f64, right: f64) -> Object {
+    match operator {
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "<=" => Object::Boolean(left <= right),
+        ">=" => Object::Boolean(left >= right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        "+" => Object::Float(left + right),
+        "-" => Object::Float(left - right),
+        "*" => Object::Float(left * right),
+        "/" | "%" if right == 0.0 => Object::error_of(ErrorKind::Arithmetic, "division by zero".to_string()),
+        "/" => Object::Float(left / right),
+        "%" => Object::Float(left % right),
+        "**" => Object::Float(left.powf(right)),
+        _ => Object::error_of(ErrorKind::Type, format!("unknown operator: FLOAT {} FLOAT", operator)),
+    }
+}
+
+fn eval_string_infix_expression(operator: &str, left: String, right: String) -> Object {
+    match operator {
+        "+" => Object::String(left + &right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "<=" => Object::Boolean(left <= right),
+        ">=" => Object::Boolean(left >= right),
+        _ => Object::error_of(ErrorKind::Type, format!("unknown operator: STRING {} STRING", operator)),
+    }
+}
+
+fn eval_index_expression(left: Object, index: Object) -> Object {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let elements = elements.borrow();
+            match usize::try_from(i).ok().and_then(|i| elements.get(i)) {
+                Some(element) => element.clone(),
+                None => Object::error_of(ErrorKind::Index, format!("index out of range: {} (len {})", i, elements.len())),
+            }
+        },
+        (Object::String(value), Object::Integer(i)) => {
+            match usize::try_from(i).ok().and_then(|i| value.chars().nth(i)) {
+                Some(ch) => Object::String(ch.to_string()),
+                None => Object::error_of(ErrorKind::Index, format!("index out of range: {} (len {})", i, value.chars().count())),
+            }
+        },
+        (left, index) => Object::error_of(ErrorKind::Type, format!("index operator not supported: {}[{}]", left.type_name(), index.type_name())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Evaluator;
+    use crate::capabilities::Capabilities;
+    use crate::limits::{LimitExceeded, Limits, DEFAULT_MAX_STACK};
+    use crate::lexer::Lexer;
+    use crate::object::{Environment, ErrorKind, Object};
+    use crate::parser::Parser;
+
+    fn test_eval(input: &str) -> Object {
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser has errors: {:?}", p.errors());
+        let env = Environment::new();
+        Evaluator::new().eval_program(&program, &env)
+    }
+
+    fn test_integer_object(obj: &Object, expected: i64) {
+        match obj {
+            Object::Integer(value) => assert_eq!(*value, expected, "object has wrong value. got {}, want {}", value, expected),
+            other => panic!("object is not Integer. got {:?}", other),
+        }
+    }
+
+    fn test_error_object(obj: &Object, expected: &str) {
+        match obj {
+            Object::Error(err) => assert_eq!(err.message, expected, "wrong error message. got {}, want {}", err.message, expected),
+            other => panic!("no error object returned. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_integer_expression(){
+        let tests = [
+            ("5", 5),
+            ("-10", -10),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * (5 + 10)", 30),
+            ("50 / 2 * 2 + 10", 60),
+            ("7 % 3", 1),
+            ("2 ** 3 ** 2", 512),
+            ("6 & 3", 2),
+            ("6 | 3", 7),
+            ("6 ^ 3", 5),
+            ("~5", -6),
+            ("1 << 4", 16),
+            ("-16 >> 2", -4),
+        ];
+
+        for (input, expected) in tests {
+            test_integer_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_eval_float_expression(){
+        let tests = [
+            ("2.5", "2.5"),
+            ("-2.5", "-2.5"),
+            ("1.5 + 1.5", "3.0"),
+            ("1 + 0.5", "1.5"),
+            ("0.5 * 4", "2.0"),
+            ("7 / 2", "3"),
+            ("7 / 2.0", "3.5"),
+            ("7.5 % 2", "1.5"),
+            ("2 ** -1", "0.5"),
+            ("2.0 ** 3", "8.0"),
+            ("1 == 1.0", "true"),
+            ("1 < 1.5", "true"),
+            ("2.5 >= 3", "false"),
+            ("let x = 1; x += 0.5; x", "1.5"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "wrong result for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression(){
+        let tests = [
+            ("true", true),
+            ("1 < 2", true),
+            ("1 >= 2", false),
+            ("2 <= 2", true),
+            ("(1 < 2) == true", true),
+            ("true != false", true),
+            ("!5", false),
+            ("!!true", true),
+            ("\"a\" == \"a\"", true),
+            ("false && x", false),
+            ("true || x", true),
+            ("1 && 0", true),
+            ("false || !true", false),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), Object::Boolean(expected), "wrong result for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_if_else_expressions(){
+        assert_eq!(test_eval("if (true) { 10 }"), Object::Integer(10));
+        assert_eq!(test_eval("if (false) { 10 }"), Object::Null);
+        assert_eq!(test_eval("if (1 > 2) { 10 } else { 20 }"), Object::Integer(20));
+    }
+
+    #[test]
+    fn test_return_statements(){
+        let tests = [
+            ("return 10; 9;", 10),
+            ("9; return 2 * 5; 9;", 10),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", 10),
+            ("let f = fn(x) { while (true) { return x; } }; f(7);", 7),
+        ];
+
+        for (input, expected) in tests {
+            test_integer_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_handling(){
+        let tests = [
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("if (10 > 1) { true + false; }", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("foobar", "identifier not found: foobar"),
+            ("let f = fn() { x = 5; }; const x = 1; f(); x", "cannot assign to constant x"),
+            ("\"a\" - \"b\"", "unknown operator: STRING - STRING"),
+            ("1 / 0", "division by zero"),
+            ("1 % 0", "division by zero"),
+            ("1 << -1", "negative right operand for <<: -1"),
+            ("1.5 / 0", "division by zero"),
+            ("1 % 0.0", "division by zero"),
+            ("1.5 & 1", "unknown operator: FLOAT & FLOAT"),
+            ("~1.5", "unknown operator: ~FLOAT"),
+            ("2 ** 62 * 4", "integer overflow"),
+            ("[1, 2][2]", "index out of range: 2 (len 2)"),
+            ("[1][-1]", "index out of range: -1 (len 1)"),
+            ("5(1)", "not a function: INTEGER"),
+            ("fn(x) { x }(1, 2)", "wrong number of arguments. got=2, want=1"),
+            ("y = 1", "identifier not found: y"),
+            ("for (x in 5) { x }", "cannot iterate over INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            test_error_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_traces(){
+        let tests = [
+            ("1 +\n  (2 / 0)", "division by zero at 2:6"),
+            ("len(1)", "argument to `len` not supported, got INTEGER at 1:4"),
+            ("let f = fn(x) { x(1) }; f(2)", "not a function: INTEGER at 1:18\n    in f called at 1:26"),
+            (
+                "let inner = fn() { [1][5] };\nlet outer = fn() { inner() };\nouter()",
+                "index out of range: 5 (len 1) at 1:23\n    in inner called at 2:25\n    in outer called at 3:6",
+            ),
+            ("fn() { -true }()", "unknown operator: -BOOLEAN at 1:8\n    in <anonymous> called at 1:15"),
+            ("map([1], fn(x) { x / 0 })", "division by zero at 1:20\n    in map called at 1:4"),
+        ];
+
+        for (input, expected) in tests {
+            match test_eval(input) {
+                Object::Error(err) => assert_eq!(err.to_string(), expected, "wrong trace for {:?}", input),
+                other => panic!("no error object returned for {:?}. got {}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_catch(){
+        let tests = [
+            ("try { 1 } catch (e) { 2 }", "1"),
+            ("try { 1 / 0 } catch (e) { e.message }", "division by zero"),
+            ("try { 1 / 0 } catch (e) { e.kind }", "ArithmeticError"),
+            ("try {\n  [1][3]\n} catch (e) { e.span }", "2:6"),
+            ("try { 1 + true } catch (e) { e.kind + \": \" + e.message }", "TypeError: type mismatch: INTEGER + BOOLEAN"),
+            ("try { len(1, 2) } catch (e) { e.kind }", "ArgumentError"),
+            ("try { throw \"boom\"; } catch (e) { [e.kind, e.message, e.span] }", "[Error, boom, 1:7]"),
+            ("try { throw 42; } catch (e) { e.message }", "42"),
+            ("try { throw \"boom\"; } catch (e) { e }", "Error: boom"),
+            ("try { throw \"boom\"; } catch (e) { type(e) }", "ERROR"),
+            ("let f = fn() { throw \"inner\"; 1 }; try { f() } catch (e) { e.message }", "inner"),
+            ("let x = 0; try { x = 1 } finally { x = 2 }; x", "2"),
+            ("try { 1 } finally { 2 }", "1"),
+            ("let x = 0; try { 1 / 0 } catch (e) { x += 1 } finally { x += 10 }; x", "11"),
+            ("let e = 1; try { throw \"x\"; } catch (e) { 2 }; e", "1"),
+            ("try { throw \"x\"; } catch (e) { 2 }; e", "ERROR: identifier not found: e"),
+            ("let f = fn() { try { return 1; } finally { 2 } }; f()", "1"),
+            ("let f = fn() { try { return 1; } finally { return 2; } }; f()", "2"),
+            ("let r = []; for (i in [1, 2, 3]) { try { if (i == 2) { break; } } finally { r = push(r, i) } }; r", "[1, 2]"),
+            ("try { try { 1 / 0 } catch (e) { throw e; } } catch (e) { e.span }", "1:15"),
+            ("try { 1 / 0 } finally { 2 }", "ERROR: division by zero"),
+            ("try { 1 / 0 } catch (e) { e.line }", "ERROR: error has no member line"),
+            ("try { 1 } finally { throw \"late\"; }", "ERROR: late"),
+            ("throw \"uncaught\";", "ERROR: uncaught"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "wrong result for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_limits_are_not_caught(){
+        let input = "let f = fn() { f() }; let caught = false; try { f() } catch (e) { caught = true } finally { caught = true }; caught";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        let mut evaluator = Evaluator::new();
+        evaluator.set_limits(Limits { max_call_depth: 20, ..Limits::default() });
+        let result = evaluator.eval_program(&program, &Environment::new());
+        assert_eq!(result, Object::LimitExceeded(LimitExceeded::CallDepth(20)));
+    }
+
+    #[test]
+    fn test_stack_limit(){
+        // Recursing through `map` and nested blocks takes far more stack per
+        // call than plain recursion. Without a call depth limit only the stack
+        // budget stops it, which must leave room on a main-thread-sized stack.
+        let limit = std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+            let input = "let f = fn(n) { first(map([n], fn(x) { if (true) { if (true) { f(x + 1) } } })) }; f(0)";
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            let mut evaluator = Evaluator::new();
+            evaluator.set_limits(Limits { max_call_depth: usize::MAX, ..Limits::default() });
+            match evaluator.eval_program(&program, &Environment::new()) {
+                Object::LimitExceeded(limit) => Some(limit),
+                _ => None,
+            }
+        }).unwrap().join().unwrap();
+        assert_eq!(limit, Some(LimitExceeded::Stack(DEFAULT_MAX_STACK)));
+    }
+
+    #[test]
+    fn test_builtins_use_the_budgets(){
+        let tests = [
+            "range(1000000)",
+            "sort(range(500))",
+            "sort_by(range(500), fn(x) { -x })",
+            "map(range(500), type)",
+            "sum(range(999))",
+        ];
+
+        for input in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            let mut evaluator = Evaluator::new();
+            evaluator.set_limits(Limits { max_steps: Some(1000), ..Limits::default() });
+            let result = evaluator.eval_program(&program, &Environment::new());
+            assert_eq!(result, Object::LimitExceeded(LimitExceeded::Steps(1000)), "wrong result for {}", input);
+        }
+
+        let mut l = Lexer::new("range(1000000000)");
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        let mut evaluator = Evaluator::new();
+        evaluator.set_limits(Limits { timeout: Some(Duration::from_millis(20)), ..Limits::default() });
+        let result = evaluator.eval_program(&program, &Environment::new());
+        assert_eq!(result, Object::LimitExceeded(LimitExceeded::Timeout(Duration::from_millis(20))));
+    }
+
+    #[test]
+    fn test_let_statements(){
+        let tests = [
+            ("let a = 5; a;", 5),
+            ("let a = 5 * 5; a;", 25),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", 15),
+        ];
+
+        for (input, expected) in tests {
+            test_integer_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_function_application(){
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            ("let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(3);", 5),
+            ("let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(10);", 3628800),
+        ];
+
+        for (input, expected) in tests {
+            test_integer_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_assignment(){
+        let tests = [
+            ("let x = 1; x = 5; x;", 5),
+            ("let x = 1; x += 5; x;", 6),
+            ("let x = 10; x -= 3; x *= 2; x /= 7; x;", 2),
+            ("let x = 1; let y = x = 7; x + y;", 14),
+            ("let x = 1; let f = fn() { x = 2; }; f(); x;", 2),
+            ("let x = 1; let f = fn() { let x = 5; x = 9; }; f(); x;", 1),
+            ("let counter = fn() { let n = 0; fn() { n += 1 } }; let c = counter(); c(); c(); c();", 3),
+            ("let a = [1, 2, 3]; a[1] = 20; a[1] += 2; a[1];", 22),
+            ("let a = [[1], [2]]; a[1][0] = 5; a[1][0];", 5),
+        ];
+
+        for (input, expected) in tests {
+            test_integer_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_deeply_nested_arrays(){
+        // Printing, comparing and freeing these must not recurse per level.
+        let input = "let a = []; let b = []; let i = 0; while (i < 200000) { a = [a]; b = [b]; i += 1; } [len(a), a == b, len(format(\"{}\", a))]";
+        assert_eq!(test_eval(input).to_string(), "[1, true, 400002]");
+    }
+
+    #[test]
+    fn test_arrays_cannot_contain_themselves(){
+        let tests = [
+            "let a = [1]; a[0] = a;",
+            "let a = [1]; a[0] = [2, [a]];",
+            "let a = [1]; let b = [a]; a[0] = b;",
+            "let a = [[1]]; let b = a[0]; b[0] = a;",
+        ];
+
+        for input in tests {
+            test_error_object(&test_eval(input), "cannot store an array inside itself");
+        }
+        assert_eq!(test_eval("let a = [1]; let b = [2]; a[0] = b; b[0] = [3]; [a, a == [[[3]]]]").to_string(), "[[[[3]]], true]");
+        assert_eq!(test_eval("let a = [1]; try { a[0] = a } catch (e) { e.kind }"), Object::String("ArgumentError".to_string()));
+    }
+
+    #[test]
+    fn test_loops(){
+        let tests = [
+            ("let i = 0; let sum = 0; while (i < 10) { i += 1; sum += i; } sum;", 55),
+            ("let i = 0; while (true) { i += 1; if (i == 5) { break; } } i;", 5),
+            ("let i = 0; let odd = 0; while (i < 10) { i += 1; if (i % 2 == 0) { continue; } odd += 1; } odd;", 5),
+            ("let sum = 0; for (x in [1, 2, 3, 4]) { sum += x; } sum;", 10),
+            ("let sum = 0; for (x in [1, 2, 3, 4]) { if (x == 3) { break; } sum += x; } sum;", 3),
+            ("let n = 0; for (ch in \"héllo\") { n += 1; } n;", 5),
+            ("let x = 1; for (x in [5]) { }; x;", 1),
+            ("let fs = []; for (i in [1, 2]) { fs = push(fs, fn() { i }); } fs[0]();", 1),
+            ("let i = 0; let y = 7; while (i < 2) { let y = i; i += 1; } y;", 7),
+            ("let i = 0; while (i < 100000) { i += 1; } i;", 100000),
+        ];
+
+        for (input, expected) in tests {
+            test_integer_object(&test_eval(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_strings_and_arrays(){
+        assert_eq!(test_eval("\"Hello\" + \" \" + \"World!\""), Object::String("Hello World!".to_string()));
+        assert_eq!(test_eval("\"héllo\"[1]"), Object::String("é".to_string()));
+        assert_eq!(test_eval("[1, 2 * 2, 3 + 3]").to_string(), "[1, 4, 6]");
+        test_integer_object(&test_eval("let i = 0; [1, 2, 3][i + 2]"), 3);
+    }
+
+    fn eval_file(path: &std::path::Path, capabilities: Capabilities) -> Object {
+        let input = std::fs::read_to_string(path).unwrap();
+        let mut l = Lexer::new(&input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser has errors: {:?}", p.errors());
+        let env = Environment::new();
+        Evaluator::with_capabilities(capabilities).eval_file_program(&program, &env, path)
+    }
+
+    #[test]
+    fn test_imports(){
+        let dir = std::env::temp_dir().join(format!("monkey_imports_{}", std::process::id()));
+        let files = [
+            ("lib/consts.mk", "let two = 2;"),
+            ("lib/math.mk", "let consts = import \"consts.mk\"; let square = fn(x) { x * x }; let four = square(consts.two); if (true) { let hidden = 1; }"),
+            ("main.mk", "let m = import \"lib/math.mk\"; [m.square(3), m.four, m.consts.two, m == import \"lib/./math.mk\"]"),
+            ("a.mk", "let b = import \"b.mk\";"),
+            ("b.mk", "let a = import \"a.mk\";"),
+            ("missing_member.mk", "(import \"lib/consts.mk\").three"),
+            ("not_a_module.mk", "let x = 1; x.y"),
+            ("bad_syntax.mk", "let = 1;"),
+            ("imports_bad_syntax.mk", "import \"bad_syntax.mk\""),
+            ("imports_missing.mk", "import \"nope.mk\""),
+            ("lib/escape.mk", "import \"../b.mk\""),
+        ];
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let mut capabilities = Capabilities::default();
+        capabilities.import.grant(None);
+
+        assert_eq!(eval_file(&dir.join("main.mk"), capabilities.clone()).to_string(), "[9, 4, 2, true]");
+
+        let tests = [
+            ("a.mk", "import cycle: "),
+            ("missing_member.mk", "module "),
+            ("not_a_module.mk", "cannot access member y of INTEGER"),
+            ("imports_bad_syntax.mk", "could not parse module "),
+            ("imports_missing.mk", "could not import \"nope.mk\": "),
+        ];
+        for (name, expected) in tests {
+            match eval_file(&dir.join(name), capabilities.clone()) {
+                Object::Error(err) => assert!(err.message.starts_with(expected), "wrong error for {}: {}", name, err.message),
+                other => panic!("expected an error for {}. got {}", name, other),
+            }
+        }
+
+        let Object::Error(err) = eval_file(&dir.join("a.mk"), capabilities.clone()) else { unreachable!() };
+        let cycle = err.message;
+        let chain = cycle.trim_start_matches("import cycle: ").split(" -> ").collect::<Vec<&str>>();
+        assert_eq!(chain.len(), 3, "wrong cycle: {}", cycle);
+        assert!(chain[0].ends_with("a.mk") && chain[1].ends_with("b.mk") && chain[2].ends_with("a.mk"), "wrong cycle: {}", cycle);
+
+        let Object::Error(err) = eval_file(&dir.join("missing_member.mk"), capabilities) else { unreachable!() };
+        let missing = err.message;
+        assert!(missing.ends_with("consts.mk\" has no member three"), "wrong error: {}", missing);
+
+        // Access to `lib` does not reach the files beside it.
+        let mut capabilities = Capabilities::default();
+        capabilities.import.grant(Some(&dir.join("lib")));
+        assert_eq!(eval_file(&dir.join("lib").join("math.mk"), capabilities.clone()), Object::Null);
+        match eval_file(&dir.join("lib").join("escape.mk"), capabilities) {
+            Object::Error(err) => assert_eq!((err.kind, err.message.as_str()), (ErrorKind::Permission, "permission denied: `import` needs import access to \"../b.mk\"")),
+            other => panic!("expected a permission error. got {}", other),
+        }
+
+        assert_eq!(
+            eval_file(&dir.join("main.mk"), Capabilities::default()),
+            Object::error("permission denied: `import` needs import access to \"lib/math.mk\"".to_string())
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use crate::lexer::Lexer;
+use crate::token::Token;
+
+const INDENT: &str = "    ";
+
+// Lays out `input` again from its tokens: every line is indented by how many
+// brackets are open at its start, and the spacing between the tokens on a line
+// is normalised. Line breaks stay where they are, except that runs of blank
+// lines collapse into one. Input with illegal tokens is left alone, since
+// there is no telling where they start and end.
+pub fn format(input: &str) -> Option<String> {
+    let tokens = Lexer::new(input).tokens();
+    if tokens.iter().any(|(token, _)| *token == Token::Illegal) {
+        return None;
+    }
+
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::new();
+    let mut depth: usize = 0;
+    let mut prev: Option<&Token> = None;
+    let mut last_line = 0;
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        if prev.is_none() || span.line > last_line {
+            if prev.is_some() {
+                out.push('\n');
+                if span.line > last_line + 1 {
+                    out.push('\n');
+                }
+            }
+            let closing = tokens[i..].iter()
+                .take_while(|(t, s)| s.line == span.line && is_closing(t))
+                .count();
+            out.push_str(&INDENT.repeat(depth.saturating_sub(closing)));
+        } else if needs_space(prev, token, tokens[..i].iter().rev().filter(|(t, _)| !is_comment(t)).nth(1).map(|(t, _)| t)) {
+            out.push(' ');
+        }
+
+        let text: String = chars[span.start..span.end].iter().collect();
+        last_line = span.line + text.matches('\n').count();
+        out.push_str(&text);
+
+        if is_opening(token) {
+            depth += 1;
+        } else if is_closing(token) {
+            depth = depth.saturating_sub(1);
+        }
+        prev = Some(token);
+    }
+    if prev.is_some() {
+        out.push('\n');
+    }
+    Some(out)
+}
+
+// Comments are skipped when looking back for the operand before an operator.
+fn is_comment(token: &Token) -> bool {
+    matches!(token, Token::Comment(_))
+}
+
+fn is_opening(token: &Token) -> bool {
+    matches!(token, Token::Lparen | Token::Lbrace | Token::Lbracket)
+}
+
+fn is_closing(token: &Token) -> bool {
+    matches!(token, Token::Rparen | Token::Rbrace | Token::Rbracket)
+}
+
+// Whether a token can end an operand, so that an operator after it is binary
+// and a bracket after it is a call or an index.
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Ident(_) | Token::Int(_) | Token::Float(_) | Token::String(_) | Token::True | Token::False
+        | Token::Rparen | Token::Rbracket | Token::Rbrace
+    )
+}
+
+fn needs_space(prev: Option<&Token>, token: &Token, before_prev: Option<&Token>) -> bool {
+    let Some(prev) = prev else {
+        return false;
+    };
+    let prefix = matches!(prev, Token::Minus | Token::Bang | Token::Tilde) && !before_prev.is_some_and(ends_operand);
+    match (prev, token) {
+        (_, Token::Comment(_)) => true,
+        _ if prefix => false,
+        (_, Token::Comma | Token::Semicolon | Token::Rparen | Token::Rbracket | Token::Dot) => false,
+        (Token::Lparen | Token::Lbracket | Token::Dot, _) => false,
+        (Token::Lbrace, Token::Rbrace) => false,
+        (Token::Function, Token::Lparen) => false,
+        (Token::Ident(_) | Token::Rparen | Token::Rbracket, Token::Lparen) => false,
+        (Token::Ident(_) | Token::String(_) | Token::Rparen | Token::Rbracket, Token::Lbracket) => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::format;
+
+    #[test]
+    fn test_format(){
+        let tests = [
+            ("let   x=1+2 ;", "let x = 1 + 2;\n"),
+            ("let add = fn (a,b){a+b};", "let add = fn(a, b) { a + b };\n"),
+            ("if(x>-1){\nputs( x [0] );\n}else{\n\n\n  -x\n}", "if (x > -1) {\n    puts(x[0]);\n} else {\n\n    -x\n}\n"),
+            ("let f = fn() {\nreturn [\n1,\n2\n];\n};", "let f = fn() {\n    return [\n        1,\n        2\n    ];\n};\n"),
+            ("m . value ( ) - !ok", "m.value() - !ok\n"),
+            ("try { x } catch(e) { e.message }", "try { x } catch (e) { e.message }\n"),
+            ("f()(1)", "f()(1)\n"),
+            ("let x=1;// one\nif(x){// two\n-x\n}  //three", "let x = 1; // one\nif (x) { // two\n    -x\n} //three\n"),
+            ("f(// first\n1)", "f( // first\n    1)\n"),
+            ("", ""),
+        ];
+
+        for (input, expected) in tests {
+            let formatted = format(input).expect("input has illegal tokens");
+            assert_eq!(formatted, expected, "wrong layout for {:?}", input);
+            assert_eq!(format(&formatted).unwrap(), formatted, "formatting is not stable for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_keeps_strings(){
+        assert_eq!(format("let s = \"a  {\n b\";\nlen(s)").unwrap(), "let s = \"a  {\n b\";\nlen(s)\n");
+    }
+
+    #[test]
+    fn test_illegal_input_is_not_formatted(){
+        assert_eq!(format("let x = @;"), None);
+        assert_eq!(format("\"open"), None);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::lexer::Lexer;
+use crate::token::Token;
+
+// How a token is coloured. Whitespace between tokens is copied unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Keyword,
+    // Numbers and booleans.
+    Literal,
+    String,
+    Operator,
+    Identifier,
+    // Brackets, commas, semicolons and dots.
+    Punctuation,
+    // Characters the lexer does not accept, and unterminated strings.
+    Illegal,
+    Comment,
+}
+
+impl Class {
+    pub fn of(token: &Token) -> Self {
+        match token {
+            Token::Ident(_) => Class::Identifier,
+            Token::Int(_) | Token::Float(_) | Token::True | Token::False => Class::Literal,
+            Token::String(_) => Class::String,
+            Token::Illegal => Class::Illegal,
+            Token::Comment(_) => Class::Comment,
+            Token::Comma | Token::Semicolon | Token::Dot
+            | Token::Lparen | Token::Rparen | Token::Lbrace | Token::Rbrace
+            | Token::Lbracket | Token::Rbracket | Token::Eof => Class::Punctuation,
+            Token::Let | Token::Const | Token::Function | Token::If | Token::Else | Token::Return
+            | Token::While | Token::For | Token::In | Token::Break | Token::Continue | Token::Import
+            | Token::Throw | Token::Try | Token::Catch | Token::Finally => Class::Keyword,
+            _ => Class::Operator,
+        }
+    }
+
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Class::Keyword => Some("1;35"),
+            Class::Literal => Some("33"),
+            Class::String => Some("32"),
+            Class::Operator => Some("36"),
+            Class::Identifier => Some("34"),
+            Class::Punctuation => None,
+            Class::Illegal => Some("1;31"),
+            Class::Comment => Some("2"),
+        }
+    }
+
+    fn css(self) -> &'static str {
+        match self {
+            Class::Keyword => "keyword",
+            Class::Literal => "literal",
+            Class::String => "string",
+            Class::Operator => "operator",
+            Class::Identifier => "identifier",
+            Class::Punctuation => "punctuation",
+            Class::Illegal => "illegal",
+            Class::Comment => "comment",
+        }
+    }
+}
+
+const STYLE: &str = "<style>
+.monkey .keyword { color: #a626a4; font-weight: bold; }
+.monkey .literal { color: #986801; }
+.monkey .string { color: #50a14f; }
+.monkey .operator { color: #0184bc; }
+.monkey .identifier { color: #4078f2; }
+.monkey .illegal { color: #e45649; font-weight: bold; text-decoration: underline wavy; }
+.monkey .comment { color: #a0a1a7; font-style: italic; }
+</style>
+";
+
+// Splits `input` into runs of text, each either a token with its class or
+// the whitespace before it, so that joining the runs gives back `input`.
+pub fn classify(input: &str) -> Vec<(Option<Class>, String)> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut runs = vec![];
+    let mut end = 0;
+    for (token, span) in Lexer::new(input).tokens() {
+        if span.start > end {
+            runs.push((None, chars[end..span.start].iter().collect()));
+        }
+        runs.push((Some(Class::of(&token)), chars[span.start..span.end].iter().collect()));
+        end = span.end;
+    }
+    if chars.len() > end {
+        runs.push((None, chars[end..].iter().collect()));
+    }
+    runs
+}
+
+pub fn to_ansi(input: &str) -> String {
+    let mut out = String::new();
+    for (class, text) in classify(input) {
+        match class.and_then(Class::ansi) {
+            Some(code) => out.push_str(&format!("\x1b[{}m{}\x1b[0m", code, text)),
+            None => out.push_str(&text),
+        }
+    }
+    out
+}
+
+// A standalone `<pre>` block with the stylesheet it needs.
+pub fn to_html(input: &str) -> String {
+    let mut out = String::from(STYLE);
+    out.push_str("<pre class=\"monkey\">");
+    for (class, text) in classify(input) {
+        let text = escape_html(&text);
+        match class {
+            Some(Class::Punctuation) | None => out.push_str(&text),
+            Some(class) => out.push_str(&format!("<span class=\"{}\">{}</span>", class.css(), text)),
+        }
+    }
+    out.push_str("</pre>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{classify, to_ansi, to_html, Class};
+
+    #[test]
+    fn test_classify(){
+        let input = "let x = fn(a) {\n  if (a >= 1.5) { return \"s\"; } @\n}; // done";
+        let runs = classify(input);
+        assert_eq!(runs.iter().map(|(_, text)| text.as_str()).collect::<String>(), input);
+
+        let classes: Vec<(Class, &str)> = runs.iter()
+            .filter_map(|(class, text)| class.map(|class| (class, text.as_str())))
+            .filter(|(class, _)| *class != Class::Punctuation)
+            .collect();
+        assert_eq!(classes, vec![
+            (Class::Keyword, "let"),
+            (Class::Identifier, "x"),
+            (Class::Operator, "="),
+            (Class::Keyword, "fn"),
+            (Class::Identifier, "a"),
+            (Class::Keyword, "if"),
+            (Class::Identifier, "a"),
+            (Class::Operator, ">="),
+            (Class::Literal, "1.5"),
+            (Class::Keyword, "return"),
+            (Class::String, "\"s\""),
+            (Class::Illegal, "@"),
+            (Class::Comment, "// done"),
+        ]);
+    }
+
+    #[test]
+    fn test_unterminated_string_is_illegal(){
+        assert_eq!(classify("x \"ab"), vec![
+            (Some(Class::Identifier), "x".to_string()),
+            (None, " ".to_string()),
+            (Some(Class::Illegal), "\"ab".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_ansi_and_html(){
+        assert_eq!(to_ansi("let x;"), "\x1b[1;35mlet\x1b[0m \x1b[34mx\x1b[0m;");
+        assert_eq!(to_ansi("1 @"), "\x1b[33m1\x1b[0m \x1b[1;31m@\x1b[0m");
+        assert_eq!(to_ansi("1 // one"), "\x1b[33m1\x1b[0m \x1b[2m// one\x1b[0m");
+
+        let html = to_html("a < \"<b>\"");
+        assert!(html.starts_with("<style>"));
+        assert!(html.ends_with(
+            "<pre class=\"monkey\"><span class=\"identifier\">a</span> <span class=\"operator\">&lt;</span> <span class=\"string\">&quot;&lt;b&gt;&quot;</span></pre>\n"
+        ));
+    }
+}
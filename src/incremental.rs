@@ -0,0 +1,382 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::ast::{ArrayLiteral, AssignExpression, BlockStatement, CallExpression, ErrorExpression, Expression, FunctionLiteral, Identifier, IfExpression, ImportExpression, IndexExpression, InfixExpression, MemberExpression, MissingExpression, PrefixExpression, Program, Statement, TryExpression};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::{Span, Token};
+
+// Source that is edited a piece at a time, as in an editor. The program is
+// kept together with the range of source each top-level statement was parsed
+// from and the errors found in it, so that an edit only has to lex and parse
+// the statements it touches. Statements after those are kept and have their
+// spans moved to where their text now is.
+pub struct Document {
+    source: String,
+    program: Program,
+    spans: Vec<Span>,
+    errors: Vec<Vec<(String, Span)>>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        let mut doc = Self { source: String::new(), program: Program::new(), spans: vec![], errors: vec![] };
+        doc.edit(0..0, source);
+        doc
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    // The source range of every top-level statement.
+    pub fn spans(&self) -> &Vec<Span> {
+        &self.spans
+    }
+
+    pub fn errors(&self) -> Vec<(String, Span)> {
+        self.errors.iter().flatten().cloned().collect()
+    }
+
+    // Replaces the chars in `range` with `text` and parses again from the
+    // statement before the edit, since how a statement ends can depend on the
+    // token after it, until parsing lines up with a statement that followed
+    // the edit. Returns the indices of the statements that were parsed.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Range<usize> {
+        let old_chars: Vec<char> = self.source.chars().collect();
+        let start = range.start.min(old_chars.len());
+        let end = range.end.clamp(start, old_chars.len());
+        let mut source: String = old_chars[..start].iter().collect();
+        source.push_str(text);
+        source.extend(&old_chars[end..]);
+        let chars: Vec<char> = source.chars().collect();
+        let edit_end = start + text.chars().count();
+
+        let first = self.spans.iter().position(|span| span.end >= start).unwrap_or(self.spans.len()).saturating_sub(1);
+        // Only whitespace and comments come before the first statement, and
+        // the edit may be inside a comment, so that parse starts at the top.
+        let from = if first == 0 { 0 } else { self.spans[first].start };
+        let (line, column) = location(&chars, from);
+
+        let mut old = std::mem::take(&mut self.program.statements);
+        let mut l = Lexer::at(&source, from, line, column);
+        let mut p = Parser::new(&mut l);
+        p.declare_top_level(&old[..first]);
+
+        let (old_line, old_column) = location(&old_chars, end);
+        let (new_line, new_column) = location(&chars, edit_end);
+        let shift = Shift {
+            from: end,
+            chars: edit_end as isize - end as isize,
+            line: old_line,
+            lines: new_line as isize - old_line as isize,
+            columns: new_column as isize - old_column as isize,
+        };
+
+        let mut statements = vec![];
+        let mut spans = vec![];
+        let mut errors = vec![];
+        let mut resume = old.len();
+        let mut can_resume = true;
+        loop {
+            let before = p.errors().len();
+            let Some((stmt, span)) = p.next_statement() else {
+                break;
+            };
+            statements.push(stmt);
+            spans.push(span);
+            errors.push(p.errors()[before..].iter().cloned().zip(p.error_spans()[before..].iter().copied()).collect());
+
+            let next = p.next_statement_start();
+            let Some(j) = (first..old.len()).find(|&j| self.spans[j].start >= end && shift.offset(self.spans[j].start) == next) else {
+                continue;
+            };
+            if !can_resume || !same_declarations(&old[..first], &old[first..j], &statements) {
+                continue;
+            }
+            // Bodies shared with closures from an evaluation cannot be
+            // moved, so the rest is parsed again instead.
+            if old[j..].iter_mut().all(|stmt| shift_statement(stmt, &shift)) {
+                resume = j;
+                break;
+            }
+            can_resume = false;
+        }
+
+        let parsed = first..first + statements.len();
+        let suffix = old.split_off(resume);
+        old.truncate(first);
+        old.extend(statements);
+        old.extend(suffix);
+        self.program.statements = old;
+
+        let mut suffix_spans = self.spans.split_off(resume);
+        let mut suffix_errors = self.errors.split_off(resume);
+        for span in suffix_spans.iter_mut() {
+            shift.apply(span);
+        }
+        for (_, span) in suffix_errors.iter_mut().flatten() {
+            shift.apply(span);
+        }
+        self.spans.truncate(first);
+        self.spans.extend(spans.into_iter().chain(suffix_spans));
+        self.errors.truncate(first);
+        self.errors.extend(errors.into_iter().chain(suffix_errors));
+        self.source = source;
+        parsed
+    }
+}
+
+// The 1-based line and column of a char offset.
+fn location(chars: &[char], offset: usize) -> (usize, usize) {
+    let before = &chars[..offset.min(chars.len())];
+    let line = before.iter().filter(|ch| **ch == '\n').count() + 1;
+    let column = before.iter().rev().take_while(|ch| **ch != '\n').count() + 1;
+    (line, column)
+}
+
+fn declarations(statements: &[Statement]) -> Vec<(&str, bool)> {
+    statements.iter().filter_map(|stmt| match stmt {
+        Statement::Let { token, name, .. } => Some((name.value.as_str(), *token == Token::Const)),
+        _ => None,
+    }).collect()
+}
+
+// Whether the statements after an edit are parsed the same as before it. The
+// parser only carries constants over from one top-level statement to the
+// next, so the names the edited statements declare must stay the same
+// unless there are no constants to keep track of.
+fn same_declarations(before: &[Statement], old: &[Statement], new: &[Statement]) -> bool {
+    let (old, new) = (declarations(old), declarations(new));
+    old == new || !declarations(before).iter().chain(old.iter()).chain(new.iter()).any(|(_, is_const)| *is_const)
+}
+
+// Moves spans at or after `from`, in the source before an edit, to where the
+// same text is after it. Columns only change on the line the edit ends on.
+struct Shift {
+    from: usize,
+    chars: isize,
+    line: usize,
+    lines: isize,
+    columns: isize,
+}
+
+impl Shift {
+    fn offset(&self, offset: usize) -> usize {
+        (offset as isize + self.chars) as usize
+    }
+
+    fn apply(&self, span: &mut Span) {
+        if span.start < self.from {
+            return;
+        }
+        if span.line == self.line {
+            span.column = (span.column as isize + self.columns) as usize;
+        }
+        span.line = (span.line as isize + self.lines) as usize;
+        span.start = self.offset(span.start);
+        span.end = self.offset(span.end);
+    }
+}
+
+fn shift_statement(stmt: &mut Statement, shift: &Shift) -> bool {
+    match stmt {
+        Statement::Let { token: _, name, value } => {
+            shift.apply(&mut name.span);
+            shift_expression(value.as_mut(), shift)
+        },
+        Statement::Return { token: _, value } => shift_expression(value.as_mut(), shift),
+        Statement::Expression { token: _, expression } => shift_expression(expression.as_mut(), shift),
+        Statement::While { token: _, condition, body } => shift_expression(condition.as_mut(), shift) && shift_block(body, shift),
+        Statement::For { token: _, variable, iterable, body } => {
+            shift.apply(&mut variable.span);
+            shift_expression(iterable.as_mut(), shift) && shift_block(body, shift)
+        },
+        Statement::Break { .. } | Statement::Continue { .. } => true,
+        Statement::Throw { token: _, value, span } => {
+            shift.apply(span);
+            shift_expression(value.as_mut(), shift)
+        },
+        Statement::Error { token: _, span } => {
+            shift.apply(span);
+            true
+        },
+    }
+}
+
+fn shift_block(block: &mut BlockStatement, shift: &Shift) -> bool {
+    block.statements.iter_mut().all(|stmt| shift_statement(stmt, shift))
+}
+
+fn shift_expressions(expressions: &mut [Box<dyn Expression>], shift: &Shift) -> bool {
+    expressions.iter_mut().all(|exp| shift_expression(exp.as_mut(), shift))
+}
+
+// Returns false when part of the expression cannot be changed.
+fn shift_expression(exp: &mut dyn Expression, shift: &Shift) -> bool {
+    let exp = exp.as_any_mut();
+    if let Some(ident) = exp.downcast_mut::<Identifier>() {
+        shift.apply(&mut ident.span);
+    } else if let Some(prefix) = exp.downcast_mut::<PrefixExpression>() {
+        shift.apply(&mut prefix.span);
+        return shift_expression(prefix.right.as_mut(), shift);
+    } else if let Some(infix) = exp.downcast_mut::<InfixExpression>() {
+        shift.apply(&mut infix.span);
+        return shift_expression(infix.left.as_mut(), shift) && shift_expression(infix.right.as_mut(), shift);
+    } else if let Some(assign) = exp.downcast_mut::<AssignExpression>() {
+        shift.apply(&mut assign.span);
+        return shift_expression(assign.target.as_mut(), shift) && shift_expression(assign.value.as_mut(), shift);
+    } else if let Some(if_exp) = exp.downcast_mut::<IfExpression>() {
+        return shift_expression(if_exp.condition.as_mut(), shift)
+            && shift_block(&mut if_exp.consequence, shift)
+            && if_exp.alternative.as_mut().is_none_or(|alt| shift_block(alt, shift));
+    } else if let Some(try_exp) = exp.downcast_mut::<TryExpression>() {
+        if let Some(catch) = &mut try_exp.catch {
+            shift.apply(&mut catch.parameter.span);
+            if !shift_block(&mut catch.body, shift) {
+                return false;
+            }
+        }
+        return shift_block(&mut try_exp.block, shift)
+            && try_exp.finally.as_mut().is_none_or(|finally| shift_block(finally, shift));
+    } else if let Some(call) = exp.downcast_mut::<CallExpression>() {
+        shift.apply(&mut call.span);
+        return shift_expression(call.function.as_mut(), shift) && shift_expressions(&mut call.arguments, shift);
+    } else if let Some(function) = exp.downcast_mut::<FunctionLiteral>() {
+        for param in function.parameters.iter_mut() {
+            shift.apply(&mut param.span);
+        }
+        return Rc::get_mut(&mut function.body).is_some_and(|body| shift_block(body, shift));
+    } else if let Some(array) = exp.downcast_mut::<ArrayLiteral>() {
+        return shift_expressions(&mut array.elements, shift);
+    } else if let Some(index) = exp.downcast_mut::<IndexExpression>() {
+        shift.apply(&mut index.span);
+        return shift_expression(index.left.as_mut(), shift) && shift_expression(index.index.as_mut(), shift);
+    } else if let Some(member) = exp.downcast_mut::<MemberExpression>() {
+        shift.apply(&mut member.span);
+        shift.apply(&mut member.property.span);
+        return shift_expression(member.left.as_mut(), shift);
+    } else if let Some(import) = exp.downcast_mut::<ImportExpression>() {
+        shift.apply(&mut import.span);
+    } else if let Some(missing) = exp.downcast_mut::<MissingExpression>() {
+        shift.apply(&mut missing.span);
+    } else if let Some(error) = exp.downcast_mut::<ErrorExpression>() {
+        shift.apply(&mut error.span);
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::Document;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    // Checks an edited document against parsing its source from scratch.
+    fn check(doc: &Document) {
+        let mut l = Lexer::new(doc.source());
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        assert_eq!(format!("{:?}", doc.program()), format!("{:?}", program), "program differs for {:?}", doc.source());
+        let errors: Vec<(String, _)> = p.errors().iter().cloned().zip(p.error_spans().iter().copied()).collect();
+        assert_eq!(doc.errors(), errors, "errors differ for {:?}", doc.source());
+        assert_eq!(doc.spans(), Document::new(doc.source()).spans(), "spans differ for {:?}", doc.source());
+    }
+
+    #[test]
+    fn test_edit_reparses_touched_statements(){
+        let mut doc = Document::new("let a = 1;\nlet b = a + 2;\nlet c = fn(x) { x * b };\nputs(c(a));\n");
+        check(&doc);
+        assert_eq!(doc.spans().len(), 4);
+
+        // Changing `2` to `20` parses `let a` and `let b` again and moves the rest.
+        let parsed = doc.edit(23..24, "20");
+        assert_eq!(parsed, 0..2);
+        assert_eq!(doc.source(), "let a = 1;\nlet b = a + 20;\nlet c = fn(x) { x * b };\nputs(c(a));\n");
+        check(&doc);
+
+        let parsed = doc.edit(27..27, "\n\nlet d = a;");
+        assert_eq!(parsed, 1..3);
+        check(&doc);
+
+        // Nothing comes before the first statement.
+        let parsed = doc.edit(0..0, "let e = \"é\"; ");
+        assert_eq!(parsed, 0..1);
+        check(&doc);
+    }
+
+    #[test]
+    fn test_edit_that_changes_statement_boundaries(){
+        let mut doc = Document::new("let a = 1\nlet b = 2;\nlet c = 3;");
+        check(&doc);
+
+        // Opening a block swallows the statements after it until it is closed.
+        doc.edit(9..9, " + fn() {");
+        check(&doc);
+        assert_eq!(doc.spans().len(), 1);
+        doc.edit(doc.source().chars().count()..doc.source().chars().count(), " };");
+        check(&doc);
+
+        // Removing a semicolon joins two statements.
+        let mut doc = Document::new("let a = 1;\n-a;\nlet c = 3;");
+        doc.edit(9..10, "");
+        check(&doc);
+        assert_eq!(doc.spans().len(), 2);
+    }
+
+    #[test]
+    fn test_edits_in_comments(){
+        let mut doc = Document::new("// a; b\nlet a = 1; // c\nlet b = a;");
+        for (range, text) in [(3..3, "x"), (4..5, ""), (0..0, " "), (24..24, "; d"), (17..18, "//")] {
+            doc.edit(range, text);
+            check(&doc);
+        }
+        assert_eq!(doc.source(), " // x; b\nlet a = //; // c; d\nlet b = a;");
+    }
+
+    #[test]
+    fn test_broken_edits(){
+        let mut doc = Document::new("let a = 1;\nlet b = a;\nb;");
+        for (range, text) in [(19..20, ""), (19..19, "(a +"), (0..3, "const")] {
+            doc.edit(range, text);
+            check(&doc);
+        }
+        assert_eq!(doc.source(), "const a = 1;\nlet b = (a +;\nb;");
+        assert!(!doc.errors().is_empty());
+
+        doc.edit(25..25, " 1)");
+        check(&doc);
+        assert_eq!(doc.errors(), vec![]);
+    }
+
+    #[test]
+    fn test_constants_after_the_edit_are_checked(){
+        let mut doc = Document::new("let x = 1;\nlet y = 2;\nx = 3;");
+        doc.edit(0..3, "const");
+        check(&doc);
+        assert_eq!(doc.errors().len(), 1);
+        assert_eq!(doc.errors()[0].0, "cannot assign to constant x at 3:1 (declared at 1:7)");
+
+        doc.edit(21..22, "5");
+        check(&doc);
+        assert_eq!(doc.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_deleting_and_restoring_each_char(){
+        let input = "const n = 2;\nlet f = fn(x) {\n    if (x > n) { x } else { [x, -x][0] }\n};\nwhile (true) { break; }\nputs(f(3));";
+        let chars: Vec<char> = input.chars().collect();
+        let mut doc = Document::new(input);
+        for (i, ch) in chars.iter().enumerate() {
+            doc.edit(i..i + 1, "");
+            check(&doc);
+            doc.edit(i..i, &ch.to_string());
+            check(&doc);
+        }
+        assert_eq!(doc.source(), input);
+    }
+}
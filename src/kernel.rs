@@ -0,0 +1,272 @@
+// A line-delimited JSON protocol on stdin and stdout for driving Monkey from
+// notebooks and editors. Every request is one JSON object with an `op` and an
+// optional `id` that is echoed back in the response:
+//
+//   {"id": 1, "op": "execute", "code": "puts(1); 1 + 1"}
+//   {"id": 1, "status": "ok", "value": "2", "type": "INTEGER", "stdout": "1\n", "warnings": []}
+//
+// `complete` and `inspect` take `code` and a `cursor` counted in chars, which
+// defaults to the end of the code. `reset` drops every binding. A failed
+// request has status "error" and a list of `errors`, each with a `kind`, a
+// `message` and a `span` that is null when the position is unknown.
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use interpreter::ast::Statement;
+use interpreter::evaluator::Evaluator;
+use interpreter::lexer::Lexer;
+use interpreter::object::{Env, Environment, Object};
+use interpreter::parser::Parser;
+use interpreter::resolver::{Diagnostic, Resolver};
+use interpreter::token::Span;
+use serde_json::{json, Value};
+
+use crate::repl::{completions, describe, word_start};
+
+pub fn start(evaluator: Evaluator) {
+    let mut kernel = Kernel::new(evaluator);
+    let mut stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = kernel.handle(&line);
+        if writeln!(stdout, "{response}").and_then(|_| stdout.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+struct Kernel {
+    evaluator: Evaluator,
+    env: Env,
+    stdout: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Kernel {
+    fn new(mut evaluator: Evaluator) -> Self {
+        // Collects what `puts` prints while a request is executed.
+        let stdout = evaluator.capture_output();
+        Self { evaluator, env: Environment::new(), stdout }
+    }
+
+    fn handle(&mut self, line: &str) -> Value {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(err) => return failure(vec![error("ProtocolError", &format!("invalid request: {}", err), None)]),
+        };
+        let code = request.get("code").and_then(Value::as_str).unwrap_or("");
+        let cursor = request.get("cursor").and_then(Value::as_u64).map(|cursor| cursor as usize);
+        let mut response = match request.get("op").and_then(Value::as_str) {
+            Some("execute") => self.execute(code),
+            Some("complete") => self.complete(code, cursor),
+            Some("inspect") => self.inspect(code, cursor),
+            Some("reset") => {
+                self.env = Environment::new();
+                json!({ "status": "ok" })
+            },
+            Some(op) => failure(vec![error("ProtocolError", &format!("unknown op {:?}", op), None)]),
+            None => failure(vec![error("ProtocolError", "request has no op", None)]),
+        };
+        response["id"] = request.get("id").cloned().unwrap_or(Value::Null);
+        response
+    }
+
+    // Like the REPL, only code ending in an expression has a value.
+    fn execute(&mut self, code: &str) -> Value {
+        let mut l = Lexer::new(code);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        if !p.errors().is_empty() {
+            return failure(p.errors().iter().zip(p.error_spans()).map(|(message, at)| error("ParseError", message, Some(*at))).collect());
+        }
+
+        let mut globals = self.evaluator.builtin_names();
+        globals.extend(self.env.borrow().names());
+        let mut r = Resolver::with_globals(&globals);
+        r.declare_constants(&self.env.borrow().constants());
+        r.resolve_program(&program);
+        let warnings: Vec<Value> = r.warnings().iter().map(|warning| diagnostic("Warning", warning)).collect();
+        if !r.errors().is_empty() {
+            let mut response = failure(r.errors().iter().map(|err| diagnostic("ResolveError", err)).collect());
+            response["warnings"] = json!(warnings);
+            return response;
+        }
+
+        let echo = matches!(program.statements.last(), Some(Statement::Expression { .. } | Statement::Return { .. }));
+        let evaluated = self.evaluator.eval_program(&program, &self.env);
+        let stdout = String::from_utf8_lossy(&self.stdout.borrow()).into_owned();
+        self.stdout.borrow_mut().clear();
+
+        let mut response = match evaluated {
+            Object::Error(err) => {
+                let mut value = error(&err.kind.to_string(), &err.message, err.span);
+                value["frames"] = err.frames.iter()
+                    .map(|frame| json!({ "function": frame.function, "call_site": span(frame.call_site) }))
+                    .collect();
+                failure(vec![value])
+            },
+            Object::LimitExceeded(limit) => failure(vec![error("LimitExceeded", &limit.to_string(), None)]),
+            value if echo => json!({ "status": "ok", "value": value.to_string(), "type": value.type_name() }),
+            _ => json!({ "status": "ok", "value": null, "type": null }),
+        };
+        response["stdout"] = json!(stdout);
+        response["warnings"] = json!(warnings);
+        response
+    }
+
+    fn complete(&self, code: &str, cursor: Option<usize>) -> Value {
+        let end = byte_offset(code, cursor);
+        let start = word_start(code, end);
+        let matches = completions(&code[start..end], &self.evaluator.builtin_names(), &self.env);
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": code[..start].chars().count(),
+            "cursor_end": code[..end].chars().count(),
+        })
+    }
+
+    // Describes the name under the cursor, looking through the session and
+    // then the builtins.
+    fn inspect(&self, code: &str, cursor: Option<usize>) -> Value {
+        let at = byte_offset(code, cursor);
+        let start = word_start(code, at);
+        let end = code[at..].find(|ch: char| !(ch.is_ascii_alphabetic() || ch == '_')).map_or(code.len(), |i| at + i);
+        let name = &code[start..end];
+        let value = if name.is_empty() {
+            None
+        } else {
+            self.env.borrow().get(name).or_else(|| self.evaluator.builtin(name))
+        };
+        match value {
+            Some(value) => json!({
+                "status": "ok",
+                "found": true,
+                "name": name,
+                "type": value.type_name(),
+                "value": describe(&value),
+            }),
+            None => json!({ "status": "ok", "found": false }),
+        }
+    }
+}
+
+// Byte offset of the char `cursor`, or the end of `code`.
+fn byte_offset(code: &str, cursor: Option<usize>) -> usize {
+    cursor.and_then(|cursor| code.char_indices().nth(cursor)).map_or(code.len(), |(i, _)| i)
+}
+
+fn failure(errors: Vec<Value>) -> Value {
+    json!({ "status": "error", "errors": errors })
+}
+
+fn error(kind: &str, message: &str, at: Option<Span>) -> Value {
+    json!({ "kind": kind, "message": message, "span": at.map(span) })
+}
+
+fn diagnostic(kind: &str, diagnostic: &Diagnostic) -> Value {
+    error(kind, &diagnostic.message, Some(diagnostic.span))
+}
+
+fn span(span: Span) -> Value {
+    json!({ "line": span.line, "column": span.column, "start": span.start, "end": span.end })
+}
+
+#[cfg(test)]
+mod test {
+    use interpreter::evaluator::Evaluator;
+    use serde_json::{json, Value};
+
+    use super::Kernel;
+
+    fn request(kernel: &mut Kernel, request: Value) -> Value {
+        kernel.handle(&request.to_string())
+    }
+
+    #[test]
+    fn test_execute(){
+        let mut kernel = Kernel::new(Evaluator::new());
+        assert_eq!(
+            request(&mut kernel, json!({ "id": 1, "op": "execute", "code": "puts(\"hi\", 20); let x = 20;" })),
+            json!({ "id": 1, "status": "ok", "value": null, "type": null, "stdout": "hi\n20\n", "warnings": [] })
+        );
+        assert_eq!(
+            request(&mut kernel, json!({ "id": 2, "op": "execute", "code": "x * 2 + 2" })),
+            json!({ "id": 2, "status": "ok", "value": "42", "type": "INTEGER", "stdout": "", "warnings": [] })
+        );
+        assert_eq!(
+            request(&mut kernel, json!({ "id": "r", "op": "reset" })),
+            json!({ "id": "r", "status": "ok" })
+        );
+        let response = request(&mut kernel, json!({ "op": "execute", "code": "x" }));
+        assert_eq!(response["status"], "error");
+        assert_eq!(response["errors"][0]["kind"], "ResolveError");
+        assert_eq!(response["errors"][0]["message"], "undefined variable x");
+    }
+
+    #[test]
+    fn test_execute_errors(){
+        let mut kernel = Kernel::new(Evaluator::new());
+        assert_eq!(
+            request(&mut kernel, json!({ "op": "execute", "code": "let f = fn() {\n  1 / 0\n};\nputs(1);\nf()" })),
+            json!({
+                "id": null,
+                "status": "error",
+                "errors": [{
+                    "kind": "ArithmeticError",
+                    "message": "division by zero",
+                    "span": { "line": 2, "column": 5, "start": 19, "end": 20 },
+                    "frames": [{ "function": "f", "call_site": { "line": 5, "column": 2, "start": 36, "end": 37 } }],
+                }],
+                "stdout": "1\n",
+                "warnings": [],
+            })
+        );
+
+        request(&mut kernel, json!({ "op": "execute", "code": "const y = 1;" }));
+        let response = request(&mut kernel, json!({ "op": "execute", "code": "y = 2; y" }));
+        assert_eq!(response["errors"][0]["kind"], "ResolveError");
+        assert_eq!(response["errors"][0]["message"], "cannot assign to constant y");
+
+        let response = request(&mut kernel, json!({ "op": "execute", "code": "let = 1;" }));
+        assert_eq!(response["errors"][0]["kind"], "ParseError");
+        assert_eq!(response["errors"][0]["span"], json!({ "line": 1, "column": 5, "start": 4, "end": 5 }));
+
+        let response = kernel.handle("{not json");
+        assert_eq!(response["errors"][0]["kind"], "ProtocolError");
+        let response = request(&mut kernel, json!({ "op": "fly" }));
+        assert_eq!(response["errors"][0]["message"], "unknown op \"fly\"");
+    }
+
+    #[test]
+    fn test_complete_and_inspect(){
+        let mut kernel = Kernel::new(Evaluator::new());
+        request(&mut kernel, json!({ "op": "execute", "code": "let total = 1; let add = fn(a, b) { a + b };" }));
+
+        assert_eq!(
+            request(&mut kernel, json!({ "op": "complete", "code": "1 + to" })),
+            json!({ "id": null, "status": "ok", "matches": ["total"], "cursor_start": 4, "cursor_end": 6 })
+        );
+        assert_eq!(
+            request(&mut kernel, json!({ "op": "complete", "code": "\"é\" + le(1)", "cursor": 8 }))["matches"],
+            json!(["len", "let"])
+        );
+        assert_eq!(
+            request(&mut kernel, json!({ "op": "inspect", "code": "add(1, 2)", "cursor": 1 })),
+            json!({ "id": null, "status": "ok", "found": true, "name": "add", "type": "FUNCTION", "value": "fn(a, b)" })
+        );
+        assert_eq!(
+            request(&mut kernel, json!({ "op": "inspect", "code": "len" }))["type"],
+            "BUILTIN"
+        );
+        assert_eq!(
+            request(&mut kernel, json!({ "op": "inspect", "code": "nothing" })),
+            json!({ "id": null, "status": "ok", "found": false })
+        );
+    }
+}
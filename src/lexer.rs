@@ -1,21 +1,33 @@
 #![allow(dead_code)]
-use crate::token::{Token, lookup_ident};
+use crate::token::{Token, Span, lookup_ident};
 
 #[derive(Default, Debug)]
 pub struct Lexer {
-    input: String,
+    input: Vec<char>,
     position: usize,
     read_position: usize,
-    ch: Option<char>
+    ch: Option<char>,
+    line: usize,
+    column: usize,
+    span: Span,
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self{
+        Self::at(input, 0, 1, 1)
+    }
+
+    // Starts lexing partway through `input`, at the char offset `start`,
+    // which is on the given 1-based line and column.
+    pub fn at(input: &str, start: usize, line: usize, column: usize) -> Self {
         let mut l = Lexer {
-            input: input.to_string(),
-            position: 0,
-            read_position: 0,
-            ch: None
+            input: input.chars().collect(),
+            position: start,
+            read_position: start,
+            ch: None,
+            line,
+            column: column - 1,
+            span: Span::default(),
         };
         l.read_char();
         l
@@ -29,21 +41,47 @@ impl Lexer {
     }
 
     fn is_digit(ch:char) -> bool{
-        ch.is_numeric()
+        ch.is_ascii_digit()
     }
     
     pub fn peek_char(&self) -> char{
-        self.input.chars().nth(self.read_position).unwrap_or('\0')
+        self.input.get(self.read_position).copied().unwrap_or('\0')
+    }
+
+    // Where the lexer is in the input, as a char offset.
+    pub fn offset(&self) -> usize {
+        self.position
+    }
+
+    pub fn input_len(&self) -> usize {
+        self.input.len()
+    }
+
+    // The input from char `start` up to char `end`.
+    pub fn text(&self, start: usize, end: usize) -> String {
+        self.input[start..end].iter().collect()
+    }
+
+    // Span of the token most recently returned by `next_token`.
+    pub fn span(&self) -> Span {
+        self.span
     }
 
     pub fn read_char(&mut self){
-        let read_pos = self.read_position;
-        if read_pos > self.input.len() {
-            self.ch = None
+        // Stay at the end once it is reached, so spans never point past it.
+        if self.read_position > self.input.len() {
+            return;
+        }
+        if self.ch == Some('\n') {
+            self.line += 1;
+            self.column = 1;
         } else {
-            self.ch = self.input.chars().nth(read_pos);
+            self.column += 1;
         }
 
+        let read_pos = self.read_position;
+        self.ch = self.input.get(read_pos).copied();
+
         self.position = read_pos;
         self.read_position += 1;
     }
@@ -56,10 +94,48 @@ impl Lexer {
             self.read_char();
         }
     }
+    // Every remaining token up to, but not including, Eof. Unlike
+    // `next_token`, this keeps the comments.
+    pub fn tokens(&mut self) -> Vec<(Token, Span)> {
+        let mut tokens = vec![];
+        loop {
+            let token = self.next_token_or_comment();
+            if token == Token::Eof {
+                return tokens;
+            }
+            tokens.push((token, self.span));
+        }
+    }
+
+    // The next token for the parser, which has no use for comments.
     pub fn next_token(&mut self) -> Token{
+        loop {
+            let token = self.next_token_or_comment();
+            if !matches!(token, Token::Comment(_)) {
+                return token;
+            }
+        }
+    }
+
+    fn next_token_or_comment(&mut self) -> Token {
         self.skip_whitespace();
+        let (start, line, column) = (self.position, self.line, self.column);
+        let token = self.read_token();
+        self.span = Span { start, end: self.position, line, column };
+        token
+    }
+
+    fn read_token(&mut self) -> Token {
         let token = match self.ch {
+            Some('-') if self.peek_char() == '=' => {
+                self.read_char();
+                Token::MinusAssign
+            }
             Some('-') => Token::Minus, 
+            Some('+') if self.peek_char() == '=' => {
+                self.read_char();
+                Token::PlusAssign
+            }
             Some('+') => Token::Plus,
             Some(';') => Token::Semicolon, 
             Some('(') => Token::Lparen, 
@@ -67,10 +143,61 @@ impl Lexer {
             Some('{') => Token::Lbrace,
             Some('}') => Token::Rbrace,
             Some(',') => Token::Comma,
+            Some('[') => Token::Lbracket,
+            Some(']') => Token::Rbracket,
+            Some('.') => Token::Dot,
+            Some('"') => match self.read_string() {
+                Some(string) => Token::String(string),
+                None => Token::Illegal,
+            },
+            Some('<') if self.peek_char() == '=' => {
+                self.read_char();
+                Token::LtEq
+            }
+            Some('<') if self.peek_char() == '<' => {
+                self.read_char();
+                Token::ShiftLeft
+            }
             Some('<') => Token::Lt,
+            Some('>') if self.peek_char() == '=' => {
+                self.read_char();
+                Token::GtEq
+            }
+            Some('>') if self.peek_char() == '>' => {
+                self.read_char();
+                Token::ShiftRight
+            }
             Some('>') => Token::Gt,
+            Some('*') if self.peek_char() == '*' => {
+                self.read_char();
+                Token::Power
+            }
+            Some('*') if self.peek_char() == '=' => {
+                self.read_char();
+                Token::AsteriskAssign
+            }
             Some('*') => Token::Asterisk,
+            Some('/') if self.peek_char() == '/' => {
+                return self.read_comment();
+            }
+            Some('/') if self.peek_char() == '=' => {
+                self.read_char();
+                Token::SlashAssign
+            }
             Some('/') => Token::Slash,
+            Some('%') => Token::Percent,
+            Some('&') if self.peek_char() == '&' => {
+                self.read_char();
+                Token::And
+            }
+            Some('&') => Token::Ampersand,
+            Some('|') if self.peek_char() == '|' => {
+                self.read_char();
+                Token::Or
+            }
+            Some('|') => Token::Pipe,
+            Some('^') => Token::Caret,
+            Some('~') => Token::Tilde,
             Some('=') if self.peek_char() == '=' => {
                 self.read_char();
                 Token::Eq
@@ -82,10 +209,10 @@ impl Lexer {
             }
             Some('!') => Token::Bang,
             Some(char) if Self::is_letter(char)  => {
-                    return lookup_ident(self.read_identifier())
+                    return lookup_ident(&self.read_identifier())
             },
             Some(char) if Self::is_digit(char)  => {
-                    return Token::Int(self.read_number().to_string());
+                    return self.read_number();
             },
             Some(_char) => Token::Illegal,
             None => Token::Eof,
@@ -94,19 +221,45 @@ impl Lexer {
         token
     }
 
-    fn read_number(&mut self) -> &str {
+    // Reads an integer, or a float when the digits are followed by a `.` and
+    // at least one more digit.
+    fn read_number(&mut self) -> Token {
+        let start = self.position;
+        self.read_digits();
+        let is_float = self.ch == Some('.') && Self::is_digit(self.peek_char());
+        if is_float {
+            self.read_char();
+            self.read_digits();
+        }
+
+        let end = self.position;
+        let literal = self.input[start..end].iter().collect();
+        if is_float {
+            Token::Float(literal)
+        } else {
+            Token::Int(literal)
+        }
+    }
+
+    // Reads from `//` up to, but not including, the end of the line.
+    fn read_comment(&mut self) -> Token {
         let start = self.position;
+        while self.ch.is_some_and(|ch| ch != '\n') {
+            self.read_char();
+        }
+        Token::Comment(self.input[start..self.position].iter().collect())
+    }
+
+    fn read_digits(&mut self) {
         while let Some(ch) = self.ch {
             if !Self::is_digit(ch) {
                 break;
             }
             self.read_char();
         }
-        let end = self.position;
-        &self.input[start..end]
     }
 
-    fn read_identifier(&mut self) -> &str {
+    fn read_identifier(&mut self) -> String {
         let start = self.position;
         while let Some(curr_char) = self.ch {
             if !Self::is_letter(curr_char) {
@@ -115,7 +268,29 @@ impl Lexer {
             self.read_char();
         }         
         let end = self.position;
-        &self.input[start..end]
+        self.input[start..end].iter().collect()
+    }
+
+    // Reads up to the closing quote, which is left as the current char.
+    // Returns `None` when the input ends before the string is terminated.
+    fn read_string(&mut self) -> Option<String> {
+        let mut string = String::new();
+        loop {
+            self.read_char();
+            match self.ch? {
+                '"' => return Some(string),
+                '\\' => {
+                    self.read_char();
+                    match self.ch? {
+                        'n' => string.push('\n'),
+                        't' => string.push('\t'),
+                        'r' => string.push('\r'),
+                        ch => string.push(ch),
+                    }
+                },
+                ch => string.push(ch),
+            }
+        }
     }
 }
 
@@ -123,6 +298,7 @@ impl Lexer {
 mod test {
     use std::borrow::BorrowMut;
     use crate::token::Token::*;
+    use crate::token::{lookup_ident, Span, KEYWORDS};
     use crate::lexer::Lexer;
     #[test]
     fn test_next_token(){
@@ -134,7 +310,7 @@ x + y;
 let result = add(five, ten);
 !-/*5;
 5 < 10 > 5;";
-        let input = String::from(raw_input);
+        let input = raw_input.to_string();
         let mut l = Lexer::new(&input);
         let tests = vec![
             Let,
@@ -192,4 +368,218 @@ let result = add(five, ten);
             assert_eq!(tok, test);
         }
     }
+
+    #[test]
+    fn test_next_token_extended_operators(){
+        let input = "a <= b >= c % d && e || f & g | h ^ ~i << 2 >> 1 ** 3;";
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            Ident("a".to_string()),
+            LtEq,
+            Ident("b".to_string()),
+            GtEq,
+            Ident("c".to_string()),
+            Percent,
+            Ident("d".to_string()),
+            And,
+            Ident("e".to_string()),
+            Or,
+            Ident("f".to_string()),
+            Ampersand,
+            Ident("g".to_string()),
+            Pipe,
+            Ident("h".to_string()),
+            Caret,
+            Tilde,
+            Ident("i".to_string()),
+            ShiftLeft,
+            Int("2".to_string()),
+            ShiftRight,
+            Int("1".to_string()),
+            Power,
+            Int("3".to_string()),
+            Semicolon,
+            Eof,
+        ];
+
+        for test in tests {
+            let tok = l.borrow_mut().next_token();
+            assert_eq!(tok, test);
+        }
+    }
+
+    #[test]
+    fn test_next_token_strings_and_brackets(){
+        let input = r#""foobar" "foo bar" "a\"b\n" "héllo" [1, 2];"#;
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            String("foobar".to_string()),
+            String("foo bar".to_string()),
+            String("a\"b\n".to_string()),
+            String("héllo".to_string()),
+            Lbracket,
+            Int("1".to_string()),
+            Comma,
+            Int("2".to_string()),
+            Rbracket,
+            Semicolon,
+            Eof,
+        ];
+
+        for test in tests {
+            let tok = l.next_token();
+            assert_eq!(tok, test);
+        }
+    }
+
+    #[test]
+    fn test_next_token_import_and_dot(){
+        let input = r#"let m = import "m.mk"; m.x"#;
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            Let,
+            Ident("m".to_string()),
+            Assign,
+            Import,
+            String("m.mk".to_string()),
+            Semicolon,
+            Ident("m".to_string()),
+            Dot,
+            Ident("x".to_string()),
+            Eof,
+        ];
+
+        for test in tests {
+            let tok = l.next_token();
+            assert_eq!(tok, test);
+        }
+    }
+
+    #[test]
+    fn test_next_token_error_handling(){
+        let input = "try { throw e; } catch (e) {} finally {}";
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            Try,
+            Lbrace,
+            Throw,
+            Ident("e".to_string()),
+            Semicolon,
+            Rbrace,
+            Catch,
+            Lparen,
+            Ident("e".to_string()),
+            Rparen,
+            Lbrace,
+            Rbrace,
+            Finally,
+            Lbrace,
+            Rbrace,
+            Eof,
+        ];
+
+        for test in tests {
+            let tok = l.next_token();
+            assert_eq!(tok, test);
+        }
+    }
+
+    #[test]
+    fn test_keywords(){
+        for keyword in KEYWORDS {
+            assert!(!matches!(lookup_ident(keyword), Ident(_)), "{} is not a keyword", keyword);
+            assert_eq!(lookup_ident(keyword).get_literal(), *keyword);
+        }
+    }
+
+    #[test]
+    fn test_next_token_floats(){
+        let input = "3.14 10 0.5 7.x";
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            Float("3.14".to_string()),
+            Int("10".to_string()),
+            Float("0.5".to_string()),
+            Int("7".to_string()),
+            Dot,
+            Ident("x".to_string()),
+            Eof,
+        ];
+
+        for test in tests {
+            let tok = l.next_token();
+            assert_eq!(tok, test);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_string(){
+        let mut l = Lexer::new("\"abc");
+        assert_eq!(l.next_token(), Illegal);
+        assert_eq!((l.span().start, l.span().end), (0, 4));
+        assert_eq!(l.next_token(), Eof);
+        assert_eq!((l.span().start, l.span().end), (4, 4));
+    }
+
+    #[test]
+    fn test_token_spans(){
+        let input = "let x = 10;\n  x == 5;";
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            (Let, Span { start: 0, end: 3, line: 1, column: 1 }),
+            (Ident("x".to_string()), Span { start: 4, end: 5, line: 1, column: 5 }),
+            (Assign, Span { start: 6, end: 7, line: 1, column: 7 }),
+            (Int("10".to_string()), Span { start: 8, end: 10, line: 1, column: 9 }),
+            (Semicolon, Span { start: 10, end: 11, line: 1, column: 11 }),
+            (Ident("x".to_string()), Span { start: 14, end: 15, line: 2, column: 3 }),
+            (Eq, Span { start: 16, end: 18, line: 2, column: 5 }),
+            (Int("5".to_string()), Span { start: 19, end: 20, line: 2, column: 8 }),
+            (Semicolon, Span { start: 20, end: 21, line: 2, column: 9 }),
+        ];
+
+        for (token, span) in tests.iter() {
+            let tok = l.next_token();
+            assert_eq!(&tok, token);
+            assert_eq!(&l.span(), span, "wrong span for {:?}", token);
+        }
+        assert_eq!(Lexer::new(input).tokens(), tests);
+    }
+
+    #[test]
+    fn test_comments(){
+        let input = "x / 2; // half of x\n// done\nx //";
+        let mut l = Lexer::new(input);
+        for token in [Ident("x".to_string()), Slash, Int("2".to_string()), Semicolon, Ident("x".to_string()), Eof] {
+            assert_eq!(l.next_token(), token);
+        }
+
+        let comments: Vec<(crate::token::Token, usize, usize)> = Lexer::new(input).tokens().into_iter()
+            .filter(|(token, _)| matches!(token, Comment(_)))
+            .map(|(token, span)| (token, span.start, span.end))
+            .collect();
+        assert_eq!(comments, vec![
+            (Comment("// half of x".to_string()), 7, 19),
+            (Comment("// done".to_string()), 20, 27),
+            (Comment("//".to_string()), 30, 32),
+        ]);
+    }
+
+    #[test]
+    fn test_next_token_assignment_operators(){
+        let input = "x = 1; x += 2; x -= 3; x *= 4; x /= 5;";
+        let mut l = Lexer::new(input);
+        let tests = vec![
+            Ident("x".to_string()), Assign, Int("1".to_string()), Semicolon,
+            Ident("x".to_string()), PlusAssign, Int("2".to_string()), Semicolon,
+            Ident("x".to_string()), MinusAssign, Int("3".to_string()), Semicolon,
+            Ident("x".to_string()), AsteriskAssign, Int("4".to_string()), Semicolon,
+            Ident("x".to_string()), SlashAssign, Int("5".to_string()), Semicolon,
+            Eof,
+        ];
+
+        for test in tests {
+            let tok = l.borrow_mut().next_token();
+            assert_eq!(tok, test);
+        }
+    }
 }
@@ -0,0 +1,22 @@
+// The lexer, parser and AST are the stable API for tools that work with
+// Monkey source. `Interpreter` is the entry point for running Monkey from a
+// host application; the remaining modules are public for the `interpreter`
+// binary and may change between releases.
+pub mod token;
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod syntax;
+pub mod incremental;
+pub mod resolver;
+pub mod highlight;
+pub mod format;
+pub mod object;
+pub mod evaluator;
+pub mod capabilities;
+pub mod limits;
+mod builtins;
+mod embed;
+
+pub use embed::{Error, Interpreter};
+pub use object::Object;
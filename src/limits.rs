@@ -0,0 +1,64 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+// Deep enough for ordinary recursive code.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 500;
+
+// Half of an 8 MiB main thread stack, leaving the rest to the host. Hosts
+// evaluating on smaller threads should lower it.
+pub const DEFAULT_MAX_STACK: usize = 4 << 20;
+
+// Bounds on a single evaluation. Each `eval_program` or host call starts a
+// fresh budget; nested programs such as imports share their importer's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    // Expressions and statements evaluated.
+    pub max_steps: Option<u64>,
+    // Nested calls of Monkey functions.
+    pub max_call_depth: usize,
+    // Bytes of Rust stack that evaluation may use. Always bounded, since
+    // expressions, blocks and calls all recurse on the Rust stack. How much
+    // each level takes depends on the code and on the build, so recursion
+    // through builtins or nested blocks can reach this before
+    // `max_call_depth`.
+    pub max_stack: usize,
+    // Array elements and string bytes created, counted when an array
+    // literal, string concatenation or builtin result is built.
+    pub max_allocation: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_stack: DEFAULT_MAX_STACK,
+            max_allocation: None,
+            timeout: None,
+        }
+    }
+}
+
+// Why an evaluation was stopped. Unlike runtime errors these abort the whole
+// evaluation and are reported to the host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitExceeded {
+    Steps(u64),
+    CallDepth(usize),
+    Stack(usize),
+    Allocation(usize),
+    Timeout(Duration),
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitExceeded::Steps(max) => write!(f, "step limit of {} exceeded", max),
+            LimitExceeded::CallDepth(max) => write!(f, "call depth limit of {} exceeded", max),
+            LimitExceeded::Stack(max) => write!(f, "stack limit of {} bytes exceeded", max),
+            LimitExceeded::Allocation(max) => write!(f, "allocation limit of {} elements exceeded", max),
+            LimitExceeded::Timeout(max) => write!(f, "time limit of {:?} exceeded", max),
+        }
+    }
+}
@@ -0,0 +1,611 @@
+// A Language Server Protocol server on stdin and stdout. Messages are JSON-RPC
+// with a `Content-Length` header. Each open document is kept parsed, and an
+// edit only parses again the statements it touches. The program is resolved
+// again after every change; the results answer every request until the next
+// change.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use std::process::ExitCode;
+
+use interpreter::evaluator::Evaluator;
+use interpreter::format;
+use interpreter::highlight::Class;
+use interpreter::incremental;
+use interpreter::lexer::Lexer;
+use interpreter::resolver::{Reference, Resolver, Symbol, SymbolKind};
+use interpreter::token::{Span, Token};
+use serde_json::{json, Value};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// Limits on what a client may send, so a bad header cannot make the server
+// allocate without bound.
+const MAX_HEADER_LINE: usize = 4096;
+const MAX_MESSAGE: usize = 64 << 20;
+
+const ERROR: u64 = 1;
+const WARNING: u64 = 2;
+
+// Text documents are synced by the ranges that changed.
+const INCREMENTAL_SYNC: u64 = 2;
+
+const TOKEN_TYPES: &[&str] = &["keyword", "number", "string", "operator", "variable", "function", "parameter", "comment"];
+
+pub fn start() -> ExitCode {
+    let mut server = Server::new();
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("lsp: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let replies = match serde_json::from_str(&message) {
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error_response(Value::Null, PARSE_ERROR, &format!("invalid message: {}", err))],
+        };
+        for reply in replies {
+            if let Err(err) = write_message(&mut output, &reply) {
+                eprintln!("lsp: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+        if server.exited {
+            return if server.shut_down { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+        }
+    }
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.by_ref().take(MAX_HEADER_LINE as u64).read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.ends_with('\n') && line.len() == MAX_HEADER_LINE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("header line is longer than {} bytes", MAX_HEADER_LINE)));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message has no Content-Length"));
+    };
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("message of {} bytes is longer than {} bytes", length, MAX_MESSAGE)));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+// Converts between char offsets, which spans use, and LSP positions, which
+// count UTF-16 code units from the start of a line.
+struct LineIndex {
+    chars: Vec<char>,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut line_starts = vec![0];
+        line_starts.extend(chars.iter().enumerate().filter(|(_, ch)| **ch == '\n').map(|(i, _)| i + 1));
+        Self { chars, line_starts }
+    }
+
+    fn width(&self, start: usize, end: usize) -> usize {
+        self.chars[start..end].iter().map(|ch| ch.len_utf16()).sum()
+    }
+
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.chars.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        (line, self.width(self.line_starts[line], offset))
+    }
+
+    fn position(&self, offset: usize) -> Value {
+        let (line, character) = self.line_column(offset);
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, span: Span) -> Value {
+        json!({ "start": self.position(span.start), "end": self.position(span.end) })
+    }
+
+    // Positions past the end of a line are clamped to it.
+    fn offset(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.chars.len();
+        };
+        let mut units = position["character"].as_u64().unwrap_or(0) as usize;
+        let mut offset = start;
+        while units > 0 && offset < self.chars.len() && self.chars[offset] != '\n' {
+            units = units.saturating_sub(self.chars[offset].len_utf16());
+            offset += 1;
+        }
+        offset
+    }
+}
+
+// An open document and what was found in it the last time it changed.
+struct Document {
+    parsed: incremental::Document,
+    index: LineIndex,
+    tokens: Vec<(Token, Span)>,
+    diagnostics: Vec<Value>,
+    symbols: Vec<Symbol>,
+    references: Vec<Reference>,
+}
+
+impl Document {
+    fn new(parsed: incremental::Document, builtins: &[String]) -> Self {
+        let index = LineIndex::new(parsed.source());
+        let tokens = Lexer::new(parsed.source()).tokens();
+        let mut r = Resolver::with_globals(builtins);
+        r.resolve_program(parsed.program());
+
+        let diagnostic = |span: Span, message: &str, severity: u64| json!({
+            "range": index.range(span),
+            "severity": severity,
+            "source": "monkey",
+            "message": message,
+        });
+        let mut diagnostics: Vec<Value> = parsed.errors().iter()
+            .map(|(message, span)| diagnostic(*span, message, ERROR))
+            .collect();
+        // Statements that did not parse declare nothing, so the names they
+        // were meant to declare would be reported as undefined.
+        if diagnostics.is_empty() {
+            diagnostics.extend(r.errors().iter().map(|d| diagnostic(d.span, &d.message, ERROR)));
+            diagnostics.extend(r.warnings().iter().map(|d| diagnostic(d.span, &d.message, WARNING)));
+        }
+
+        Self {
+            parsed,
+            index,
+            tokens,
+            diagnostics,
+            symbols: r.symbols().clone(),
+            references: r.references().clone(),
+        }
+    }
+
+    // The symbol declared or used at `offset`, and the span of its name there.
+    fn symbol_at(&self, offset: usize) -> Option<(usize, Span)> {
+        let contains = |span: &Span| span.start <= offset && offset <= span.end;
+        self.symbols.iter().position(|symbol| contains(&symbol.span))
+            .map(|i| (i, self.symbols[i].span))
+            .or_else(|| self.references.iter().find(|r| contains(&r.span)).map(|r| (r.symbol, r.span)))
+    }
+
+    fn identifier_at(&self, offset: usize) -> Option<(&str, Span)> {
+        self.tokens.iter().find_map(|(token, span)| match token {
+            Token::Ident(name) if span.start <= offset && offset <= span.end => Some((name.as_str(), *span)),
+            _ => None,
+        })
+    }
+
+    fn semantic_type(&self, token: &Token, span: Span) -> Option<usize> {
+        match Class::of(token) {
+            Class::Keyword => Some(0),
+            Class::Literal => Some(1),
+            Class::String => Some(2),
+            Class::Operator => Some(3),
+            Class::Identifier => match self.symbol_at(span.start).map(|(i, _)| self.symbols[i].kind) {
+                Some(SymbolKind::Function) => Some(5),
+                Some(SymbolKind::Parameter | SymbolKind::CaughtError) => Some(6),
+                _ => Some(4),
+            },
+            Class::Comment => Some(7),
+            Class::Punctuation | Class::Illegal => None,
+        }
+    }
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    builtins: Vec<String>,
+    shut_down: bool,
+    exited: bool,
+}
+
+impl Server {
+    fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            builtins: Evaluator::new().builtin_names(),
+            shut_down: false,
+            exited: false,
+        }
+    }
+
+    // Returns the messages to send back: a response for a request, and
+    // diagnostics whenever a document changes.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            return vec![];
+        };
+        let params = &message["params"];
+        let Some(id) = message.get("id").cloned() else {
+            return self.notify(method, params);
+        };
+        if self.shut_down {
+            return vec![error_response(id, INVALID_REQUEST, "server is shut down")];
+        }
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let result = match (method, self.documents.get(uri)) {
+            ("initialize", _) => capabilities(),
+            ("shutdown", _) => {
+                self.shut_down = true;
+                Value::Null
+            },
+            ("textDocument/definition", Some(doc)) => definition(uri, doc, params),
+            ("textDocument/references", Some(doc)) => references(uri, doc, params),
+            ("textDocument/hover", Some(doc)) => hover(doc, params, &self.builtins),
+            ("textDocument/documentSymbol", Some(doc)) => document_symbols(doc),
+            ("textDocument/semanticTokens/full", Some(doc)) => semantic_tokens(doc),
+            ("textDocument/formatting", Some(doc)) => formatting(doc),
+            (
+                "textDocument/definition" | "textDocument/references" | "textDocument/hover"
+                | "textDocument/documentSymbol" | "textDocument/semanticTokens/full" | "textDocument/formatting",
+                None,
+            ) => Value::Null,
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, &format!("unknown method {}", method))],
+        };
+        vec![response(id, result)]
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+        match method {
+            "exit" => {
+                self.exited = true;
+                vec![]
+            },
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.update(uri, incremental::Document::new(text))
+            },
+            "textDocument/didChange" => {
+                let Some(doc) = self.documents.remove(&uri) else {
+                    return vec![];
+                };
+                let mut parsed = doc.parsed;
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let new_text = change["text"].as_str().unwrap_or("");
+                    match change.get("range") {
+                        Some(range) => {
+                            let index = LineIndex::new(parsed.source());
+                            parsed.edit(index.offset(&range["start"])..index.offset(&range["end"]), new_text);
+                        },
+                        None => parsed = incremental::Document::new(new_text),
+                    }
+                }
+                self.update(uri, parsed)
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![notification("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }))]
+            },
+            _ => vec![],
+        }
+    }
+
+    fn update(&mut self, uri: String, parsed: incremental::Document) -> Vec<Value> {
+        let doc = Document::new(parsed, &self.builtins);
+        let diagnostics = json!({ "uri": uri, "diagnostics": doc.diagnostics });
+        self.documents.insert(uri, doc);
+        vec![notification("textDocument/publishDiagnostics", diagnostics)]
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": { "openClose": true, "change": INCREMENTAL_SYNC },
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "hoverProvider": true,
+            "documentSymbolProvider": true,
+            "documentFormattingProvider": true,
+            "semanticTokensProvider": {
+                "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                "full": true,
+            },
+        },
+        "serverInfo": { "name": "monkey" },
+    })
+}
+
+fn definition(uri: &str, doc: &Document, params: &Value) -> Value {
+    match doc.symbol_at(doc.index.offset(&params["position"])) {
+        Some((symbol, _)) => json!({ "uri": uri, "range": doc.index.range(doc.symbols[symbol].span) }),
+        None => Value::Null,
+    }
+}
+
+fn references(uri: &str, doc: &Document, params: &Value) -> Value {
+    let Some((symbol, _)) = doc.symbol_at(doc.index.offset(&params["position"])) else {
+        return Value::Null;
+    };
+    let mut spans = vec![];
+    if params["context"]["includeDeclaration"].as_bool().unwrap_or(false) {
+        spans.push(doc.symbols[symbol].span);
+    }
+    spans.extend(doc.references.iter().filter(|r| r.symbol == symbol).map(|r| r.span));
+    spans.sort_by_key(|span| span.start);
+    spans.iter().map(|span| json!({ "uri": uri, "range": doc.index.range(*span) })).collect()
+}
+
+// Shows what kind of binding a name is, and its type when the value it was
+// declared with makes that plain.
+fn hover(doc: &Document, params: &Value, builtins: &[String]) -> Value {
+    let offset = doc.index.offset(&params["position"]);
+    let (label, span) = match doc.symbol_at(offset) {
+        Some((symbol, span)) => {
+            let symbol = &doc.symbols[symbol];
+            match &symbol.detail {
+                Some(detail) => (format!("({}) {}: {}", symbol.kind, symbol.name, detail), span),
+                None => (format!("({}) {}", symbol.kind, symbol.name), span),
+            }
+        },
+        None => match doc.identifier_at(offset) {
+            Some((name, span)) if builtins.iter().any(|builtin| builtin == name) => (format!("(builtin) {}", name), span),
+            _ => return Value::Null,
+        },
+    };
+    json!({
+        "contents": { "kind": "markdown", "value": format!("```monkey\n{}\n```", label) },
+        "range": doc.index.range(span),
+    })
+}
+
+fn document_symbols(doc: &Document) -> Value {
+    doc.symbols.iter().filter(|symbol| symbol.top_level).map(|symbol| {
+        let kind = match symbol.kind {
+            SymbolKind::Function => 12,
+            SymbolKind::Constant => 14,
+            _ => 13,
+        };
+        json!({
+            "name": symbol.name,
+            "detail": symbol.detail,
+            "kind": kind,
+            "range": doc.index.range(symbol.span),
+            "selectionRange": doc.index.range(symbol.span),
+        })
+    }).collect()
+}
+
+// Each token is five numbers: its line and start relative to the previous
+// token, its length, its type and its modifiers.
+fn semantic_tokens(doc: &Document) -> Value {
+    let mut data = vec![];
+    let (mut prev_line, mut prev_start) = (0, 0);
+    for (token, span) in doc.tokens.iter() {
+        let Some(kind) = doc.semantic_type(token, *span) else {
+            continue;
+        };
+        let (line, start) = doc.index.line_column(span.start);
+        let delta_start = if line == prev_line { start - prev_start } else { start };
+        data.extend([line - prev_line, delta_start, doc.index.width(span.start, span.end), kind, 0]);
+        (prev_line, prev_start) = (line, start);
+    }
+    json!({ "data": data })
+}
+
+fn formatting(doc: &Document) -> Value {
+    match format::format(doc.parsed.source()) {
+        Some(formatted) if formatted != doc.parsed.source() => json!([{
+            "range": doc.index.range(Span { start: 0, end: doc.index.chars.len(), ..Span::default() }),
+            "newText": formatted,
+        }]),
+        _ => json!([]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use super::{read_message, write_message, Server};
+
+    const URI: &str = "file:///test.mk";
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        let mut replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "languageId": "monkey", "version": 1, "text": text } },
+        }));
+        assert_eq!(replies.len(), 1);
+        replies.remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let mut replies = server.handle(&json!({ "jsonrpc": "2.0", "id": 7, "method": method, "params": params }));
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0]["id"], 7);
+        replies.remove(0)["result"].take()
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    #[test]
+    fn test_message_framing(){
+        let mut input = Cursor::new(b"Content-Length: 13\r\nContent-Type: x\r\n\r\n{\"id\":1}\n    ".to_vec());
+        assert_eq!(read_message(&mut input).unwrap().unwrap(), "{\"id\":1}\n    ");
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let mut input = Cursor::new(b"Content-Length: 99999999999999\r\n\r\n{}".to_vec());
+        let err = read_message(&mut input).unwrap_err();
+        assert_eq!(err.to_string(), "message of 99999999999999 bytes is longer than 67108864 bytes");
+        let mut input = Cursor::new([b"X-Long: ".to_vec(), vec![b'a'; 5000]].concat());
+        assert_eq!(read_message(&mut input).unwrap_err().to_string(), "header line is longer than 4096 bytes");
+
+        let mut output = vec![];
+        write_message(&mut output, &json!({ "id": "é" })).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Content-Length: 11\r\n\r\n{\"id\":\"é\"}");
+    }
+
+    #[test]
+    fn test_lifecycle(){
+        let mut server = Server::new();
+        let result = request(&mut server, "initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["capabilities"]["hoverProvider"], true);
+        assert_eq!(result["capabilities"]["textDocumentSync"]["change"], 2);
+
+        let reply = server.handle(&json!({ "jsonrpc": "2.0", "id": 2, "method": "workspace/frobnicate" }));
+        assert_eq!(reply[0]["error"]["code"], -32601);
+
+        assert_eq!(request(&mut server, "shutdown", Value::Null), Value::Null);
+        let reply = server.handle(&json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": at(0, 0) }));
+        assert_eq!(reply[0]["error"]["code"], -32600);
+        assert!(!server.exited);
+        server.handle(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+        assert!(server.exited);
+    }
+
+    #[test]
+    fn test_diagnostics(){
+        let mut server = Server::new();
+        let published = open(&mut server, "let x = 1;\nlet y = ;");
+        assert_eq!(published["method"], "textDocument/publishDiagnostics");
+        assert_eq!(published["params"]["uri"], URI);
+        assert_eq!(published["params"]["diagnostics"], json!([{
+            "range": range((1, 8), (1, 9)),
+            "severity": 1,
+            "source": "monkey",
+            "message": "no prefix parse function for ; found",
+        }]));
+
+        let published = open(&mut server, "let f = fn() { let unused = 1; missing };");
+        let diagnostics: Vec<(&str, u64)> = published["params"]["diagnostics"].as_array().unwrap().iter()
+            .map(|d| (d["message"].as_str().unwrap(), d["severity"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(diagnostics, vec![("undefined variable missing", 1), ("unused variable unused", 2)]);
+    }
+
+    #[test]
+    fn test_navigation(){
+        let mut server = Server::new();
+        open(&mut server, "let add = fn(a, b) { a + b };\nlet total = add(1, 2);\nadd(total, len(\"é\"));");
+
+        assert_eq!(request(&mut server, "textDocument/definition", at(2, 1)), json!({ "uri": URI, "range": range((0, 4), (0, 7)) }));
+        assert_eq!(request(&mut server, "textDocument/definition", at(0, 21)), json!({ "uri": URI, "range": range((0, 13), (0, 14)) }));
+        assert_eq!(request(&mut server, "textDocument/definition", at(0, 0)), Value::Null);
+
+        let mut params = at(0, 5);
+        params["context"] = json!({ "includeDeclaration": true });
+        let locations: Vec<Value> = request(&mut server, "textDocument/references", params).as_array().unwrap().iter()
+            .map(|location| location["range"].clone())
+            .collect();
+        assert_eq!(locations, vec![range((0, 4), (0, 7)), range((1, 12), (1, 15)), range((2, 0), (2, 3))]);
+
+        let hover = |server: &mut Server, line, character| request(server, "textDocument/hover", at(line, character))["contents"]["value"].clone();
+        assert_eq!(hover(&mut server, 2, 0), "```monkey\n(function) add: fn(a, b)\n```");
+        assert_eq!(hover(&mut server, 0, 14), "```monkey\n(parameter) a\n```");
+        assert_eq!(hover(&mut server, 2, 5), "```monkey\n(variable) total\n```");
+        assert_eq!(hover(&mut server, 2, 12), "```monkey\n(builtin) len\n```");
+        assert_eq!(request(&mut server, "textDocument/hover", at(1, 18)), Value::Null);
+    }
+
+    #[test]
+    fn test_symbols_and_semantic_tokens(){
+        let mut server = Server::new();
+        open(&mut server, "const max = 3;\nlet f = fn(n) {\n  let inner = n;\n  inner\n};");
+
+        let symbols: Vec<(String, u64, Value)> = request(&mut server, "textDocument/documentSymbol", at(0, 0))
+            .as_array().unwrap().iter()
+            .map(|s| (s["name"].as_str().unwrap().to_string(), s["kind"].as_u64().unwrap(), s["detail"].clone()))
+            .collect();
+        assert_eq!(symbols, vec![
+            ("max".to_string(), 14, json!("INTEGER")),
+            ("f".to_string(), 12, json!("fn(n)")),
+        ]);
+
+        open(&mut server, "let f = fn(n) { // calls f\n  f(n)\n};");
+        let data = request(&mut server, "textDocument/semanticTokens/full", at(0, 0))["data"].clone();
+        assert_eq!(data, json!([
+            0, 0, 3, 0, 0,
+            0, 4, 1, 5, 0,
+            0, 2, 1, 3, 0,
+            0, 2, 2, 0, 0,
+            0, 3, 1, 6, 0,
+            0, 5, 10, 7, 0,
+            1, 2, 1, 5, 0,
+            0, 2, 1, 6, 0,
+        ]));
+    }
+
+    #[test]
+    fn test_incremental_change_and_formatting(){
+        let mut server = Server::new();
+        open(&mut server, "let x = 1;\nlet y = x;");
+        let replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [
+                    { "range": range((1, 8), (1, 9)), "text": "z" },
+                    { "range": range((0, 10), (0, 10)), "text": "\nlet  z=x+1;" },
+                ],
+            },
+        }));
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+        assert_eq!(server.documents[URI].parsed.source(), "let x = 1;\nlet  z=x+1;\nlet y = z;");
+
+        let edits = request(&mut server, "textDocument/formatting", json!({ "textDocument": { "uri": URI }, "options": {} }));
+        assert_eq!(edits, json!([{
+            "range": range((0, 0), (2, 10)),
+            "newText": "let x = 1;\nlet z = x + 1;\nlet y = z;\n",
+        }]));
+    }
+}
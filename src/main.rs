@@ -1,27 +1,237 @@
-mod lexer;
-mod token;
+mod kernel;
+mod lsp;
 mod repl;
-mod ast;
-mod parser;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use interpreter::capabilities::Capabilities;
+use interpreter::evaluator::Evaluator;
+use interpreter::highlight;
+use interpreter::lexer::Lexer;
+use interpreter::limits::Limits;
+use interpreter::object::{Environment, Object};
+use interpreter::parser::Parser;
+use interpreter::resolver::Resolver;
 
 
 mod welcome {
-    use users::{get_current_uid, get_user_by_uid};
+    // Looking up the OS user pulls in the `users` crate, so it is only done
+    // with the `user-greeting` feature.
+    #[cfg(feature = "user-greeting")]
+    fn user_name() -> Option<String> {
+        use users::{get_current_uid, get_user_by_uid};
+        get_user_by_uid(get_current_uid())?.name().to_str().map(str::to_string)
+    }
+
+    #[cfg(not(feature = "user-greeting"))]
+    fn user_name() -> Option<String> {
+        None
+    }
+
     pub fn print(){
-        let welcome_msg = "Hey there! This is the monkey programming language";
-        if let Some(user) = get_user_by_uid(get_current_uid()) {
-            if let Some(name) = user.name().to_str() {
-                println!("Hey {name}! This is the monkey programming language");
-            } else {
-                println!("{welcome_msg}");
-            }
+        match user_name() {
+            Some(name) => println!("Hey {name}! This is the monkey programming language"),
+            None => println!("Hey there! This is the monkey programming language"),
         }
-        println!("Feel free to type in commands!");
+        println!("Feel free to type in commands! Type :help for the REPL commands.");
+    }
+}
+
+const USAGE: &str = "usage: interpreter [OPTIONS] [SCRIPT [ARGS...]]
+       interpreter kernel [OPTIONS]
+       interpreter lsp [--stdio]
+       interpreter highlight [--html] FILE
+options: --allow-import[=PATH] --allow-read[=PATH] --allow-write[=PATH] --allow-env
+         --max-steps=N --max-depth=N --max-allocation=N --timeout=MS";
+
+struct Options {
+    capabilities: Capabilities,
+    limits: Limits,
+    script: Option<String>,
+}
 
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<&str>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+// Grants and limits are only taken from flags before the script path;
+// everything after it is passed to the script through `args()`.
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut capabilities = Capabilities::default();
+    let mut limits = Limits::default();
+    let mut script = None;
+    for arg in args {
+        if script.is_some() {
+            capabilities.args.push(arg);
+            continue;
+        }
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        match flag {
+            "--allow-import" => capabilities.import.grant(value.map(Path::new)),
+            "--allow-read" => capabilities.read.grant(value.map(Path::new)),
+            "--allow-write" => capabilities.write.grant(value.map(Path::new)),
+            "--allow-env" if value.is_none() => capabilities.env = true,
+            "--max-steps" => limits.max_steps = Some(parse_number(flag, value)?),
+            "--max-depth" => limits.max_call_depth = parse_number(flag, value)?,
+            "--max-allocation" => limits.max_allocation = Some(parse_number(flag, value)?),
+            "--timeout" => limits.timeout = Some(Duration::from_millis(parse_number(flag, value)?)),
+            _ if flag.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => script = Some(arg),
+        }
     }
+    // Without `--allow-import` a script may only import the files beside it,
+    // or, for the REPL and kernel, those in the working directory.
+    let home = script.as_deref().and_then(|script| Path::new(script).parent()).filter(|dir| !dir.as_os_str().is_empty());
+    capabilities.import.grant(Some(home.unwrap_or(Path::new("."))));
+    Ok(Options { capabilities, limits, script })
 }
 
-fn main() {
-    welcome::print();
-    repl::start();
+fn run_script(path: &str, mut evaluator: Evaluator) -> ExitCode {
+    let input = match fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("could not read {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut l = Lexer::new(&input);
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+    if !p.errors().is_empty() {
+        repl::print_parse_errors(p.errors());
+        return ExitCode::FAILURE;
+    }
+
+    let mut r = Resolver::with_globals(&evaluator.builtin_names());
+    r.resolve_program(&program);
+    for warning in r.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    if !r.errors().is_empty() {
+        repl::print_parse_errors(&r.errors().iter().map(|e| e.to_string()).collect::<Vec<String>>());
+        return ExitCode::FAILURE;
+    }
+
+    let env = Environment::new();
+    match evaluator.eval_file_program(&program, &env, Path::new(path)) {
+        Object::Error(err) => eprintln!("ERROR: {err}"),
+        result if result.is_error() => eprintln!("{result}"),
+        _ => return ExitCode::SUCCESS,
+    }
+    ExitCode::FAILURE
+}
+
+// Prints FILE coloured for a terminal, or as HTML with `--html`.
+fn highlight_file(args: impl Iterator<Item = String>) -> ExitCode {
+    let mut html = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--html" => html = true,
+            _ if arg.starts_with('-') || path.is_some() => {
+                eprintln!("unexpected argument {arg}\n{USAGE}");
+                return ExitCode::FAILURE;
+            },
+            _ => path = Some(arg),
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("highlight needs a file\n{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    match fs::read_to_string(&path) {
+        Ok(input) if html => print!("{}", highlight::to_html(&input)),
+        Ok(input) => print!("{}", highlight::to_ansi(&input)),
+        Err(err) => {
+            eprintln!("could not read {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "highlight").is_some() {
+        return highlight_file(args);
+    }
+    if args.next_if(|arg| arg == "lsp").is_some() {
+        // Editors commonly pass `--stdio`, which is the only transport.
+        if let Some(arg) = args.find(|arg| arg != "--stdio") {
+            eprintln!("unexpected argument {arg}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+        return lsp::start();
+    }
+    let kernel = args.next_if(|arg| arg == "kernel").is_some();
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if kernel && options.script.is_some() {
+        eprintln!("kernel does not take a script\n{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut evaluator = Evaluator::with_capabilities(options.capabilities);
+    evaluator.set_limits(options.limits);
+    if kernel {
+        kernel::start(evaluator);
+        return ExitCode::SUCCESS;
+    }
+    match options.script {
+        Some(path) => run_script(&path, evaluator),
+        None => {
+            welcome::print();
+            repl::start(evaluator);
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::parse_args;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().into_iter()
+    }
+
+    #[test]
+    fn test_import_access(){
+        let dir = std::env::temp_dir().join(format!("monkey_import_access_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let script = dir.join("lib").join("main.mk");
+
+        let options = parse_args(args(&[script.to_str().unwrap()])).unwrap();
+        assert!(options.capabilities.import.allows(&dir.join("lib").join("util.mk")));
+        assert!(!options.capabilities.import.allows(&dir.join("secret.mk")));
+        assert!(!options.capabilities.import.allows(Path::new("/etc/passwd")));
+
+        let flag = format!("--allow-import={}", dir.display());
+        let options = parse_args(args(&[&flag, script.to_str().unwrap()])).unwrap();
+        assert!(options.capabilities.import.allows(&dir.join("secret.mk")));
+        assert!(!options.capabilities.import.allows(Path::new("/etc/passwd")));
+
+        let options = parse_args(args(&["--allow-import"])).unwrap();
+        assert!(options.capabilities.import.allows(Path::new("/etc/passwd")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
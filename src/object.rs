@@ -0,0 +1,418 @@
+#![allow(dead_code)]
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use crate::ast::{BlockStatement, Identifier};
+use crate::token::Span;
+use crate::evaluator::Evaluator;
+use crate::limits::LimitExceeded;
+
+pub type BuiltinFunction = fn(&mut Evaluator, Vec<Object>) -> Object;
+
+pub type Env = Rc<RefCell<Environment>>;
+
+// A builtin registered by the host at runtime. Unlike `BuiltinFunction` it
+// may capture state, and it reports errors as plain messages.
+pub struct NativeFunction {
+    pub name: String,
+    pub function: Box<dyn Fn(Vec<Object>) -> Result<Object, String>>,
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+// A function call that was active when a runtime error was raised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    // The `let` name of the function, or `<anonymous>`.
+    pub function: String,
+    pub call_site: Span,
+}
+
+// What went wrong, as seen by a `catch` block through `e.kind`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    // Raised by `throw` or by anything not more specific below.
+    Error,
+    Type,
+    Name,
+    Argument,
+    Index,
+    Arithmetic,
+    Import,
+    Permission,
+    Io,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ErrorKind::Error => "Error",
+            ErrorKind::Type => "TypeError",
+            ErrorKind::Name => "NameError",
+            ErrorKind::Argument => "ArgumentError",
+            ErrorKind::Index => "IndexError",
+            ErrorKind::Arithmetic => "ArithmeticError",
+            ErrorKind::Import => "ImportError",
+            ErrorKind::Permission => "PermissionError",
+            ErrorKind::Io => "IOError",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    // The innermost expression that failed, when known.
+    pub span: Option<Span>,
+    // Innermost call first.
+    pub frames: Vec<Frame>,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { kind: ErrorKind::Error, message: message.into(), span: None, frames: vec![] }
+    }
+}
+
+// Renders like parser diagnostics, followed by one line per frame.
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}", span)?;
+        }
+        for frame in self.frames.iter() {
+            write!(f, "\n    in {} called at {}", frame.function, frame.call_site)?;
+        }
+        Ok(())
+    }
+}
+
+// The elements of an array. Arrays may be nested arbitrarily deep, so
+// dropping, printing and comparing them never recurse once per level.
+#[derive(Debug, Clone, Default)]
+pub struct Elements(Vec<Object>);
+
+impl Deref for Elements {
+    type Target = Vec<Object>;
+
+    fn deref(&self) -> &Vec<Object> {
+        &self.0
+    }
+}
+
+impl DerefMut for Elements {
+    fn deref_mut(&mut self) -> &mut Vec<Object> {
+        &mut self.0
+    }
+}
+
+// Frees nested arrays one after another from a list instead of each freeing
+// the next.
+impl Drop for Elements {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.0);
+        while let Some(value) = pending.pop() {
+            if let Object::Array(elements) = value {
+                if let Ok(elements) = Rc::try_unwrap(elements) {
+                    pending.append(&mut elements.into_inner().0);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Object {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array(Rc<RefCell<Elements>>),
+    Function {
+        name: Option<String>,
+        parameters: Vec<Identifier>,
+        body: Rc<BlockStatement>,
+        env: Env,
+    },
+    Builtin {
+        name: &'static str,
+        function: BuiltinFunction,
+    },
+    Native(Rc<NativeFunction>),
+    // The top-level `let` bindings of an imported file.
+    Module {
+        path: String,
+        exports: Rc<HashMap<String, Object>>,
+    },
+    Null,
+    ReturnValue(Box<Object>),
+    Break,
+    Continue,
+    Error(Box<RuntimeError>),
+    // An error bound by `catch`. Unlike `Error` it is an ordinary value.
+    Caught(Rc<RuntimeError>),
+    // Aborts evaluation; propagated like an error but never handled by it.
+    LimitExceeded(LimitExceeded),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Integer(_) => "INTEGER",
+            Self::Float(_) => "FLOAT",
+            Self::Boolean(_) => "BOOLEAN",
+            Self::String(_) => "STRING",
+            Self::Array(_) => "ARRAY",
+            Self::Function { .. } => "FUNCTION",
+            Self::Builtin { .. } | Self::Native(_) => "BUILTIN",
+            Self::Module { .. } => "MODULE",
+            Self::Null => "NULL",
+            Self::ReturnValue(_) => "RETURN_VALUE",
+            Self::Break => "BREAK",
+            Self::Continue => "CONTINUE",
+            Self::Error(_) | Self::Caught(_) => "ERROR",
+            Self::LimitExceeded(_) => "LIMIT_EXCEEDED",
+        }
+    }
+
+    pub fn new_array(elements: Vec<Object>) -> Self {
+        Self::Array(Rc::new(RefCell::new(Elements(elements))))
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::Error(Box::new(RuntimeError::new(message)))
+    }
+
+    pub fn error_of(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self::Error(Box::new(RuntimeError { kind, ..RuntimeError::new(message) }))
+    }
+
+    // The error for a call with `got` arguments to a function or builtin that
+    // takes `want`: a count, a range such as "1-2", or "1 or more".
+    pub fn wrong_arity(got: usize, want: impl Display) -> Self {
+        Self::error_of(ErrorKind::Argument, format!("wrong number of arguments. got={}, want={}", got, want))
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error(_) | Self::LimitExceeded(_))
+    }
+
+    // Whether `array` is this value or is nested somewhere inside it. Arrays
+    // are never stored inside themselves, so printing and comparing them
+    // always ends.
+    pub fn contains_array(&self, array: &Rc<RefCell<Elements>>) -> bool {
+        let mut seen: Vec<*const RefCell<Elements>> = vec![];
+        let mut pending = vec![self.clone()];
+        while let Some(value) = pending.pop() {
+            let Self::Array(elements) = value else {
+                continue;
+            };
+            if Rc::ptr_eq(&elements, array) {
+                return true;
+            }
+            if !seen.contains(&Rc::as_ptr(&elements)) {
+                seen.push(Rc::as_ptr(&elements));
+                pending.extend(elements.borrow().iter().cloned());
+            }
+        }
+        false
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Null => false,
+            Self::Boolean(value) => *value,
+            _ => true,
+        }
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Array(a), Self::Array(b)) => arrays_equal(a, b),
+            (Self::Function { body: a, env: env_a, .. }, Self::Function { body: b, env: env_b, .. }) => {
+                Rc::ptr_eq(a, b) && Rc::ptr_eq(env_a, env_b)
+            },
+            (Self::Builtin { name: a, .. }, Self::Builtin { name: b, .. }) => a == b,
+            (Self::Native(a), Self::Native(b)) => Rc::ptr_eq(a, b),
+            (Self::Module { exports: a, .. }, Self::Module { exports: b, .. }) => Rc::ptr_eq(a, b),
+            (Self::Null, Self::Null) => true,
+            (Self::ReturnValue(a), Self::ReturnValue(b)) => a == b,
+            (Self::Break, Self::Break) => true,
+            (Self::Continue, Self::Continue) => true,
+            // Where an error was raised does not change what it is.
+            (Self::Error(a), Self::Error(b)) => a.message == b.message,
+            (Self::Caught(a), Self::Caught(b)) => a.kind == b.kind && a.message == b.message,
+            (Self::LimitExceeded(a), Self::LimitExceeded(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// Compares nested arrays from a list of pairs still to check.
+fn arrays_equal(a: &Rc<RefCell<Elements>>, b: &Rc<RefCell<Elements>>) -> bool {
+    let mut pending = vec![(Rc::clone(a), Rc::clone(b))];
+    while let Some((a, b)) = pending.pop() {
+        if Rc::ptr_eq(&a, &b) {
+            continue;
+        }
+        let (a, b) = (a.borrow(), b.borrow());
+        if a.len() != b.len() {
+            return false;
+        }
+        for (x, y) in a.iter().zip(b.iter()) {
+            match (x, y) {
+                (Object::Array(x), Object::Array(y)) => pending.push((Rc::clone(x), Rc::clone(y))),
+                (x, y) if x != y => return false,
+                _ => {},
+            }
+        }
+    }
+    true
+}
+
+// Writes an array, keeping the nested arrays and separators still to write
+// on a list.
+fn write_array(f: &mut std::fmt::Formatter<'_>, array: &Rc<RefCell<Elements>>) -> std::fmt::Result {
+    enum Piece {
+        Value(Object),
+        Text(&'static str),
+    }
+
+    let mut pending = vec![Piece::Value(Object::Array(Rc::clone(array)))];
+    while let Some(piece) = pending.pop() {
+        match piece {
+            Piece::Text(text) => write!(f, "{}", text)?,
+            Piece::Value(Object::Array(elements)) => {
+                write!(f, "[")?;
+                pending.push(Piece::Text("]"));
+                for (i, el) in elements.borrow().iter().enumerate().rev() {
+                    pending.push(Piece::Value(el.clone()));
+                    if i > 0 {
+                        pending.push(Piece::Text(", "));
+                    }
+                }
+            },
+            Piece::Value(value) => write!(f, "{}", value)?,
+        }
+    }
+    Ok(())
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{:?}", value),
+            Self::Boolean(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+            Self::Array(elements) => write_array(f, elements),
+            Self::Function { parameters, body, .. } => {
+                write!(f, "fn({}) {{\n{}\n}}", parameters.iter().map(|p| p.to_string()).collect::<Vec<String>>().join(", "), body)
+            },
+            Self::Builtin { .. } | Self::Native(_) => write!(f, "builtin function"),
+            Self::Module { path, .. } => write!(f, "module {:?}", path),
+            Self::Null => write!(f, "null"),
+            Self::ReturnValue(value) => write!(f, "{}", value),
+            Self::Break => write!(f, "break"),
+            Self::Continue => write!(f, "continue"),
+            Self::Error(err) => write!(f, "ERROR: {}", err.message),
+            Self::Caught(err) => write!(f, "{}: {}", err.kind, err.message),
+            Self::LimitExceeded(limit) => write!(f, "ERROR: {}", limit),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    // Names in `store` bound with `const`.
+    constants: HashSet<String>,
+    outer: Option<Env>,
+}
+
+// Why a name could not be bound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindError {
+    Unbound,
+    Constant,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    pub fn new_enclosed(outer: &Env) -> Env {
+        Rc::new(RefCell::new(Self {
+            outer: Some(Rc::clone(outer)),
+            ..Self::default()
+        }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(obj) => Some(obj.clone()),
+            None => self.outer.as_ref()?.borrow().get(name),
+        }
+    }
+
+    // Names bound in this scope, not including outer scopes.
+    pub fn names(&self) -> Vec<String> {
+        self.store.keys().cloned().collect()
+    }
+
+    // Names bound with `const` in this scope, which later input in the same
+    // session may not assign or declare again.
+    pub fn constants(&self) -> Vec<String> {
+        self.constants.iter().cloned().collect()
+    }
+
+    // Binds `name` in this scope, shadowing any outer binding.
+    pub fn set(&mut self, name: &str, value: Object) {
+        self.store.insert(name.to_string(), value);
+    }
+
+    // Binds `name` like `let` or `const`, unless this scope already has a
+    // constant by that name.
+    pub fn declare(&mut self, name: &str, value: Object, is_const: bool) -> Result<(), BindError> {
+        if self.constants.contains(name) {
+            return Err(BindError::Constant);
+        }
+        if is_const {
+            self.constants.insert(name.to_string());
+        }
+        self.set(name, value);
+        Ok(())
+    }
+
+    // Rebinds the nearest existing `name`.
+    pub fn assign(&mut self, name: &str, value: Object) -> Result<(), BindError> {
+        if let Some(slot) = self.store.get_mut(name) {
+            if self.constants.contains(name) {
+                return Err(BindError::Constant);
+            }
+            *slot = value;
+            return Ok(());
+        }
+        match self.outer.as_ref() {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => Err(BindError::Unbound),
+        }
+    }
+}
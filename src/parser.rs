@@ -1,19 +1,32 @@
 #![allow(dead_code)]
 
+use std::collections::HashMap;
+use std::rc::Rc;
+
 use crate::ast::{Expression, Identifier, InfixExpression, IntegerLiteral, PrefixExpression, Program, Statement};
 use crate::lexer::Lexer;
-use crate::token::Token;
-use crate::ast::{BlockStatement, Boolean, CallExpression, FunctionLiteral, IfExpression};
+use crate::syntax::{GreenNode, SyntaxKind, TreeBuilder};
+use crate::token::{Span, Token};
+use crate::ast::{ArrayLiteral, AssignExpression, BlockStatement, Boolean, CallExpression, CatchClause, ErrorExpression, FloatLiteral, FunctionLiteral, IfExpression, ImportExpression, IndexExpression, MemberExpression, MissingExpression, StringLiteral, TryExpression};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum Precedence {
     Lowest = 0,
-    Equals = 1,
-    LessGreater = 2,
-    Sum = 3,
-    Product = 4,
-    Prefix = 5,
-    Call = 6,
+    Assign = 1,
+    LogicalOr = 2,
+    LogicalAnd = 3,
+    BitwiseOr = 4,
+    BitwiseXor = 5,
+    BitwiseAnd = 6,
+    Equals = 7,
+    LessGreater = 8,
+    Shift = 9,
+    Sum = 10,
+    Product = 11,
+    Prefix = 12,
+    Exponent = 13,
+    Call = 14,
+    Index = 15,
 }
 
 impl Precedence {
@@ -23,15 +36,33 @@ impl Precedence {
 
     pub fn token(token: Token) -> Self {
         match token {
+            Token::Assign => Self::Assign,
+            Token::PlusAssign => Self::Assign,
+            Token::MinusAssign => Self::Assign,
+            Token::AsteriskAssign => Self::Assign,
+            Token::SlashAssign => Self::Assign,
+            Token::Or => Self::LogicalOr,
+            Token::And => Self::LogicalAnd,
+            Token::Pipe => Self::BitwiseOr,
+            Token::Caret => Self::BitwiseXor,
+            Token::Ampersand => Self::BitwiseAnd,
             Token::Eq => Self::Equals,
             Token::NotEq => Self::Equals,
             Token::Lt => Self::LessGreater,
             Token::Gt => Self::LessGreater,
+            Token::LtEq => Self::LessGreater,
+            Token::GtEq => Self::LessGreater,
+            Token::ShiftLeft => Self::Shift,
+            Token::ShiftRight => Self::Shift,
             Token::Plus => Self::Sum,
             Token::Minus => Self::Sum,
             Token::Slash => Self::Product,
             Token::Asterisk => Self::Product,
+            Token::Percent => Self::Product,
+            Token::Power => Self::Exponent,
             Token::Lparen => Self::Call,
+            Token::Lbracket => Self::Index,
+            Token::Dot => Self::Index,
             _ => Self::Lowest
         }
     }
@@ -42,16 +73,68 @@ pub struct Parser<'parser> {
     cur_token: Option<Token>,
     peek_token: Option<Token>,
     errors: Vec<String>,
+    // Where each of `errors` was found.
+    error_spans: Vec<Span>,
+    loop_depth: usize,
+    cur_span: Span,
+    peek_span: Span,
+    // Names bound in each enclosing function, loop or catch scope, innermost
+    // last. The value is the span of the declaration for `const` bindings and
+    // `None` for `let` bindings and parameters.
+    scopes: Vec<HashMap<String, Option<Span>>>,
+    // Name for the function literal about to be parsed as a `let` value, so
+    // runtime errors can name the function.
+    let_name: Option<String>,
+    // The syntax tree read so far, when the parser is building one.
+    tree: Option<TreeBuilder>,
+    // How many expressions and statements enclose the one being parsed.
+    depth: usize,
+    // Set once `depth` passes `MAX_NESTING`, so that the enclosing code that
+    // fails because of it does not report an error at every level.
+    too_deep: bool,
 }
 
+// Deep enough for any program written by hand, and shallow enough that
+// parsing and every later pass over the tree fit on a 2 MiB thread stack,
+// even in debug builds.
+const MAX_NESTING: usize = 256;
+
 use Precedence::*;
 
+fn has_prefix_parse_fn(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Ident(_) | Token::Int(_) | Token::Float(_) | Token::String(_) | Token::Lbracket | Token::Bang | Token::Minus
+        | Token::Tilde | Token::Lparen | Token::True | Token::False | Token::If | Token::Try | Token::Function | Token::Import
+    )
+}
+
 impl<'parser> Parser<'parser> {
     pub fn new(lexer: &'parser mut Lexer) -> Self {
+        Self::start(lexer, None)
+    }
+
+    // A parser that also builds a lossless syntax tree of what it reads,
+    // which `finish_syntax_tree` returns once the program is parsed.
+    pub fn with_syntax_tree(lexer: &'parser mut Lexer) -> Self {
+        let tree = TreeBuilder::new(lexer.offset());
+        Self::start(lexer, Some(tree))
+    }
+
+    fn start(lexer: &'parser mut Lexer, tree: Option<TreeBuilder>) -> Self {
         let mut p = Self {
             cur_token: None,
             peek_token: None,
             errors: vec![],
+            error_spans: vec![],
+            loop_depth: 0,
+            cur_span: Span::default(),
+            peek_span: Span::default(),
+            scopes: vec![HashMap::new()],
+            let_name: None,
+            tree,
+            depth: 0,
+            too_deep: false,
             lexer,
         };
         p.next_token();
@@ -63,17 +146,21 @@ impl<'parser> Parser<'parser> {
         let cur_token = self.cur_token.clone()?;
 
         match token {
-            Token::Ident(_) => Some(Box::new(Identifier {
-                value: cur_token.get_literal(),
-                token: cur_token.clone(),
-            })),
+            Token::Ident(_) => Some(Box::new(Identifier::new(cur_token, self.cur_span))),
             Token::Int(_) => self.parse_integer_literal(),
+            Token::Float(_) => self.parse_float_literal(),
+            Token::String(value) => Some(Box::new(StringLiteral { token: cur_token.clone(), value: value.clone() })),
+            Token::Lbracket => self.parse_array_literal(),
             Token::Bang => self.parse_prefix_expression(),
             Token::Minus => self.parse_prefix_expression(),
+            Token::Tilde => self.parse_prefix_expression(),
+            Token::Lparen => self.parse_grouped_expression(),
             Token::True => self.parse_boolean(),
             Token::False => self.parse_boolean(),
             Token::If => self.parse_if_expression(),
+            Token::Try => self.parse_try_expression(),
             Token::Function => self.parse_function_literal(),
+            Token::Import => self.parse_import_expression(),
             _ => None,
         }
     }
@@ -89,44 +176,162 @@ impl<'parser> Parser<'parser> {
             Token::NotEq => self.parse_infix_expression(left),
             Token::Lt => self.parse_infix_expression(left),
             Token::Gt => self.parse_infix_expression(left),
+            Token::LtEq => self.parse_infix_expression(left),
+            Token::GtEq => self.parse_infix_expression(left),
+            Token::Percent => self.parse_infix_expression(left),
+            Token::And => self.parse_infix_expression(left),
+            Token::Or => self.parse_infix_expression(left),
+            Token::Ampersand => self.parse_infix_expression(left),
+            Token::Pipe => self.parse_infix_expression(left),
+            Token::Caret => self.parse_infix_expression(left),
+            Token::ShiftLeft => self.parse_infix_expression(left),
+            Token::ShiftRight => self.parse_infix_expression(left),
+            Token::Power => self.parse_infix_expression(left),
+            Token::Assign => self.parse_assign_expression(left),
+            Token::PlusAssign => self.parse_assign_expression(left),
+            Token::MinusAssign => self.parse_assign_expression(left),
+            Token::AsteriskAssign => self.parse_assign_expression(left),
+            Token::SlashAssign => self.parse_assign_expression(left),
             Token::Lparen => self.parse_call_expression(left).map(|expr| expr as Box<dyn Expression>),
+            Token::Lbracket => self.parse_index_expression(left),
+            Token::Dot => self.parse_member_expression(left),
             _ => None,
         }
     }
+    // Broken input still gives a program: statements that cannot be parsed
+    // become `Statement::Error` and missing or broken expressions become
+    // `MissingExpression` and `ErrorExpression`, next to the errors reported.
     pub fn parse_program(&mut self) -> Program {
         let mut program = Program::new();
-        while self.cur_token != Some(Token::Eof) {
-            if let Some(stmt) = self.parse_statement() {
-                program.statements.push(stmt);
-            }
-            self.next_token();
+        while let Some((stmt, _)) = self.next_statement() {
+            program.statements.push(stmt);
         }
         program
     }
 
+    // Parses the statement at the current token and returns it with the
+    // range of source it was read from, or None at the end of the input.
+    pub fn next_statement(&mut self) -> Option<(Statement, Span)> {
+        if self.cur_token_is(Token::Eof) {
+            return None;
+        }
+        let start = self.cur_span;
+        let stmt = self.parse_statement_or_recover()?;
+        let span = Span { end: self.cur_span.end, ..start };
+        self.next_token();
+        Some((stmt, span))
+    }
+
+    // Where the statement after the one just parsed starts.
+    pub fn next_statement_start(&self) -> usize {
+        self.cur_span.start
+    }
+
+    // Lets a parse that starts partway through a program know the names the
+    // statements before it bound, so constants keep being checked.
+    pub fn declare_top_level(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            if let Statement::Let { token, name, .. } = stmt {
+                self.declare(name, *token == Token::Const);
+            }
+        }
+    }
+
+    // The syntax tree of everything read, for a parser made with
+    // `with_syntax_tree`.
+    pub fn finish_syntax_tree(&mut self) -> Option<GreenNode> {
+        self.tree.take().map(|tree| tree.finish(self.lexer))
+    }
+
     pub fn errors(&self) -> &Vec<String> {
         &self.errors
     }
 
+    pub fn error_spans(&self) -> &Vec<Span> {
+        &self.error_spans
+    }
+
+    fn error(&mut self, msg: String, span: Span) {
+        if self.too_deep {
+            return;
+        }
+        self.errors.push(msg);
+        self.error_spans.push(span);
+    }
+
     fn peek_error(&mut self, t: Token) {
         let msg = format!(
             "expected next token to be {}, got {:?} instead",
             &t.get_literal(),
             self.peek_token.as_ref().unwrap().get_literal()
         );
-        self.errors.push(msg);
+        self.error(msg, self.peek_span);
     }
 
     fn next_token(&mut self) {
         self.cur_token = self.peek_token.take();
+        self.cur_span = self.peek_span;
         self.peek_token = Some(self.lexer.next_token());
+        self.peek_span = self.lexer.span();
+        if let (Some(tree), Some(token)) = (&mut self.tree, &self.cur_token) {
+            tree.token(self.lexer, token, self.cur_span);
+        }
+    }
+
+    // Where a syntax tree node that starts at the current token begins.
+    fn checkpoint(&self) -> usize {
+        self.tree.as_ref().map_or(0, TreeBuilder::checkpoint)
+    }
+
+    // Makes everything read since `checkpoint` into a syntax tree node.
+    fn wrap(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        if let Some(tree) = &mut self.tree {
+            tree.wrap(checkpoint, kind);
+        }
+    }
+
+    // Counts one more level of nesting, returning false once there are too
+    // many to parse.
+    fn nest(&mut self) -> bool {
+        self.depth += 1;
+        if self.depth > MAX_NESTING && !self.too_deep {
+            self.error(format!("code is nested more than {} levels deep", MAX_NESTING), self.cur_span);
+            self.too_deep = true;
+        }
+        self.depth <= MAX_NESTING
+    }
+
+    fn unnest(&mut self) {
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.too_deep = false;
+        }
+    }
+
+    fn parse_statement_or_recover(&mut self) -> Option<Statement> {
+        let checkpoint = self.checkpoint();
+        let (token, start) = (self.cur_token.clone()?, self.cur_span);
+        let parsed = if self.nest() { self.parse_statement() } else { None };
+        // A statement that was cut off for being too deep is skipped whole,
+        // rather than parsing what is left of it as more statements.
+        let stmt = match parsed {
+            Some(stmt) if !self.too_deep => stmt,
+            _ => self.recover(token, start),
+        };
+        self.unnest();
+        self.wrap(checkpoint, SyntaxKind::of_statement(&stmt));
+        Some(stmt)
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
         if let Some(cur_tok) = self.cur_token.as_ref() {
             match cur_tok {
-                Token::Let => self.parse_let_statement(),
+                Token::Let | Token::Const => self.parse_let_statement(),
                 Token::Return => self.parse_return_statement(),
+                Token::While => self.parse_while_statement(),
+                Token::For => self.parse_for_statement(),
+                Token::Break | Token::Continue => self.parse_loop_control_statement(),
+                Token::Throw => self.parse_throw_statement(),
                 _ => self.parse_expression_statement(),
             }
         } else {
@@ -134,33 +339,83 @@ impl<'parser> Parser<'parser> {
         }
     }
 
+    // Skips the rest of a statement that could not be parsed: up to its `;`,
+    // the `}` closing the enclosing block or the keyword starting the next
+    // statement, stepping over whole bracketed groups on the way.
+    fn recover(&mut self, token: Token, start: Span) -> Statement {
+        let mut depth = 0;
+        loop {
+            match self.cur_token {
+                Some(Token::Lparen | Token::Lbrace | Token::Lbracket) => depth += 1,
+                Some(Token::Rparen | Token::Rbrace | Token::Rbracket) if depth > 0 => depth -= 1,
+                Some(Token::Semicolon) if depth == 0 => break,
+                _ => {},
+            }
+            let next_statement = matches!(
+                self.peek_token,
+                Some(Token::Rbrace | Token::Let | Token::Const | Token::Return | Token::While | Token::For | Token::Throw)
+            );
+            if self.peek_token_is(Token::Eof) || (depth == 0 && next_statement) {
+                break;
+            }
+            self.next_token();
+        }
+        Statement::Error { token, span: Span { end: self.cur_span.end, ..start } }
+    }
+
     fn parse_let_statement(&mut self) -> Option<Statement> {
+        let token = self.cur_token.clone()?;
         if !matches!(self.peek_token, Some(Token::Ident(_))) {
+            self.peek_error(Token::Ident(String::from("identifier")));
             return None;
         }
 
         self.next_token();
 
-        let name = Some(Identifier::new(self.cur_token.clone()?));
+        let ident = Identifier::new(self.cur_token.clone()?, self.cur_span);
+        self.declare(&ident, token == Token::Const);
         if !self.expect_peek(Token::Assign) {
             return None;
         }
 
-        // TODO: Skipping expression until semicolon cuz we noobies
-        self.next_token();
-
-        let value =  self.parse_expression(Lowest);
+        if self.peek_token_is(Token::Function) {
+            self.let_name = Some(ident.value.clone());
+        }
+        let value = self.parse_operand(Lowest);
+        self.let_name = None;
         if self.peek_token_is(Token::Semicolon) {
             self.next_token();
         }
 
-        Some(Statement::Let { token: Token::Let, name,  value })
+        Some(Statement::Let { token, name: ident, value })
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
+    fn declare(&mut self, ident: &Identifier, is_const: bool) {
+        let scope = self.scopes.last_mut().expect("parser has no scope");
+        if let Some(Some(declared)) = scope.get(&ident.value) {
+            let msg = format!(
+                "cannot redeclare constant {} at {} (declared at {})",
+                ident.value, ident.span, declared
+            );
+            self.error(msg, ident.span);
+            return;
+        }
+        scope.insert(ident.value.clone(), is_const.then_some(ident.span));
+    }
 
-        self.next_token();
-        let value = self.parse_expression(Lowest);
+    fn check_const_assignment(&mut self, ident: &Identifier) {
+        let declared = self.scopes.iter().rev().find_map(|scope| scope.get(&ident.value));
+        if let Some(Some(declared)) = declared {
+            let msg = format!(
+                "cannot assign to constant {} at {} (declared at {})",
+                ident.value, ident.span, declared
+            );
+            self.error(msg, ident.span);
+        }
+    }
+
+    fn parse_return_statement(&mut self) -> Option<Statement> {
+        let value = self.parse_operand(Lowest);
 
         if self.peek_token_is(Token::Semicolon){
             self.next_token();
@@ -169,20 +424,157 @@ impl<'parser> Parser<'parser> {
         Some(Statement::Return { token: Token::Return,  value })
     }
 
+    fn parse_while_statement(&mut self) -> Option<Statement> {
+        let token = self.cur_token.clone()?;
+        if !self.expect_peek(Token::Lparen) {
+            return None
+        }
+
+        let condition = self.parse_operand(Lowest);
+
+        if !self.expect_peek(Token::Rparen) {
+            return None
+        }
+        if !self.expect_peek(Token::Lbrace) {
+            return None
+        }
+
+        let body = self.parse_loop_body(HashMap::new());
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(Statement::While { token, condition, body })
+    }
+
+    fn parse_for_statement(&mut self) -> Option<Statement> {
+        let token = self.cur_token.clone()?;
+        if !self.expect_peek(Token::Lparen) {
+            return None
+        }
+
+        if !matches!(self.peek_token, Some(Token::Ident(_))) {
+            self.peek_error(Token::Ident(String::from("identifier")));
+            return None
+        }
+        self.next_token();
+        let variable = Identifier::new(self.cur_token.clone()?, self.cur_span);
+
+        if !self.expect_peek(Token::In) {
+            return None
+        }
+
+        let iterable = self.parse_operand(Lowest);
+
+        if !self.expect_peek(Token::Rparen) {
+            return None
+        }
+        if !self.expect_peek(Token::Lbrace) {
+            return None
+        }
+
+        let body = self.parse_loop_body(HashMap::from([(variable.value.clone(), None)]));
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(Statement::For { token, variable, iterable, body })
+    }
+
+    // Each iteration runs in a scope of its own, which starts out with the
+    // loop variable of a `for`.
+    fn parse_loop_body(&mut self, scope: HashMap<String, Option<Span>>) -> BlockStatement {
+        self.loop_depth += 1;
+        self.scopes.push(scope);
+        let body = self.parse_block_statement();
+        self.scopes.pop();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn parse_loop_control_statement(&mut self) -> Option<Statement> {
+        let token = self.cur_token.clone()?;
+        if self.loop_depth == 0 {
+            self.error(format!("{} statement outside of a loop", token.get_literal()), self.cur_span);
+        }
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        }
+
+        match token {
+            Token::Break => Some(Statement::Break { token }),
+            _ => Some(Statement::Continue { token }),
+        }
+    }
+
+    fn parse_throw_statement(&mut self) -> Option<Statement> {
+        let token = self.cur_token.clone()?;
+        let span = self.cur_span;
+        let value = self.parse_operand(Lowest);
+
+        if self.peek_token_is(Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(Statement::Throw { token, value, span })
+    }
+
     fn no_prefix_parse_fn_errors(&mut self, t: Token) {
         let msg = format!("no prefix parse function for {} found", t.get_literal());
-        self.errors.push(msg);
+        self.error(msg, self.cur_span);
+    }
+
+    // Parses the expression after the current token. It is never dropped:
+    // when the source ends the expression before it starts, as in
+    // `let x = ;`, a `MissingExpression` stands in for it and nothing is
+    // consumed, and when it starts but cannot be parsed an `ErrorExpression`
+    // covers the tokens read.
+    fn parse_operand(&mut self, precedence: Precedence) -> Box<dyn Expression> {
+        let ends = matches!(
+            self.peek_token,
+            None | Some(Token::Eof | Token::Semicolon | Token::Comma | Token::Rparen | Token::Rbrace | Token::Rbracket)
+        );
+        if ends {
+            let peek = self.peek_token.clone().unwrap_or(Token::Eof);
+            self.error(format!("no prefix parse function for {} found", peek.get_literal()), self.peek_span);
+            if let Some(tree) = &mut self.tree {
+                tree.empty(self.lexer, SyntaxKind::Missing, self.peek_span.start);
+            }
+            return Box::new(MissingExpression { span: Span { end: self.peek_span.start, ..self.peek_span } });
+        }
+
+        self.next_token();
+        let (token, start) = (self.cur_token.clone().unwrap_or(Token::Eof), self.cur_span);
+        match self.parse_expression(precedence) {
+            Some(exp) => exp,
+            None => Box::new(ErrorExpression { token, span: Span { end: self.cur_span.end, ..start } }),
+        }
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> Option<Box<dyn Expression>> {
+        let exp = if self.nest() { self.parse_expression_unchecked(precedence) } else { None };
+        self.unnest();
+        exp
+    }
+
+    fn parse_expression_unchecked(&mut self, precedence: Precedence) -> Option<Box<dyn Expression>> {
         let t = self.cur_token.clone();
+        let checkpoint = self.checkpoint();
         let mut left_exp;
 
         if let Some(token) = t {
             match self.prefix_parse_fns(&token) {
-                Some(exp) => left_exp = Some(exp),
+                Some(exp) => {
+                    left_exp = Some(exp);
+                    self.wrap(checkpoint, SyntaxKind::of_prefix(&token));
+                },
                 None => {
-                    self.no_prefix_parse_fn_errors(self.cur_token.clone()?); 
+                    // A prefix parse function that failed has said why.
+                    if !has_prefix_parse_fn(&token) {
+                        self.no_prefix_parse_fn_errors(token);
+                    }
+                    self.wrap(checkpoint, SyntaxKind::Error);
                     return None
                 }
             };
@@ -196,52 +588,91 @@ impl<'parser> Parser<'parser> {
             let prev_left = left_exp?;
             self.next_token();
             left_exp = self.infix_parse_fns(&pt, prev_left);
+            let kind = if left_exp.is_some() { SyntaxKind::of_infix(&pt) } else { SyntaxKind::Error };
+            self.wrap(checkpoint, kind);
         }
 
         left_exp
     }
 
-    fn parse_integer_literal(&self) -> Option<Box<dyn Expression>> {
-        let cur_token = self.cur_token.clone();
-        if let Some(token) = cur_token {
-            let parsed_token = token.get_literal().parse::<i32>();
-            return match parsed_token {
-                Ok(val) => Some(Box::new(IntegerLiteral::new(token, val))),
-                Err(e) => {
-                    eprintln!("This token cannot pe parsed as integer");
-                    eprintln!("{}", e);
-                    None
-                }
-            };
+    fn parse_integer_literal(&mut self) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        match token.get_literal().parse::<i64>() {
+            Ok(value) => Some(Box::new(IntegerLiteral::new(token, value))),
+            Err(_) => {
+                self.error(format!("could not parse {} as integer", token.get_literal()), self.cur_span);
+                None
+            }
         }
+    }
 
-        None
+    fn parse_float_literal(&mut self) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        match token.get_literal().parse::<f64>() {
+            Ok(value) => Some(Box::new(FloatLiteral { token, value })),
+            Err(_) => {
+                self.error(format!("could not parse {} as float", token.get_literal()), self.cur_span);
+                None
+            }
+        }
     }
 
     fn parse_prefix_expression(&mut self) -> Option<Box<dyn Expression>> {
         let token = self.cur_token.clone()?;
-        self.next_token();
+        let span = self.cur_span;
 
         Some(Box::new(PrefixExpression {
             token: token.clone(),
             operator: token.get_literal(),
-            right: self.parse_expression(Precedence::Prefix)?,
+            right: self.parse_operand(Precedence::Prefix),
+            span,
         }))
     }
 
     fn parse_infix_expression(&mut self, left: Box<dyn Expression>) -> Option<Box<dyn Expression>> {
-        let precedence = self.cur_precedence();
+        let mut precedence = self.cur_precedence();
         let cur_token = self.cur_token.clone()?;
-        self.next_token();
+        let span = self.cur_span;
+        // `**` is right-associative: parse the right side one level lower so
+        // that `a ** b ** c` groups as `a ** (b ** c)`.
+        if cur_token == Token::Power {
+            precedence = Precedence::Prefix;
+        }
 
         Some(Box::new(InfixExpression{
             token: cur_token.clone(),
             operator: cur_token.get_literal(),
-            right: self.parse_expression(precedence)?,
-            left
+            right: self.parse_operand(precedence),
+            left,
+            span,
+        }))
+    }
+
+    fn parse_assign_expression(&mut self, target: Box<dyn Expression>) -> Option<Box<dyn Expression>> {
+        let cur_token = self.cur_token.clone()?;
+        let span = self.cur_span;
+        if !Self::is_assignable(target.as_ref()) {
+            self.error(format!("invalid assignment target: {}", target), target.span().unwrap_or(span));
+            return None
+        }
+        if let Some(ident) = target.as_any().downcast_ref::<Identifier>() {
+            self.check_const_assignment(ident);
+        }
+
+        // Assignment is right-associative: `a = b = c` is `a = (b = c)`.
+        Some(Box::new(AssignExpression{
+            token: cur_token.clone(),
+            operator: cur_token.get_literal(),
+            value: self.parse_operand(Lowest),
+            target,
+            span,
         }))
     }
 
+    fn is_assignable(target: &dyn Expression) -> bool {
+        target.as_any().is::<Identifier>() || target.as_any().is::<IndexExpression>()
+    }
+
     fn parse_boolean(&self) -> Option<Box<dyn Expression>> {
         Some(Box::new(
             Boolean{
@@ -256,8 +687,7 @@ impl<'parser> Parser<'parser> {
             return None
         }
 
-        self.next_token();
-        let condition = self.parse_expression(Precedence::Lowest)?;
+        let condition = self.parse_operand(Lowest);
 
         if !self.expect_peek(Token::Rparen){
             return None
@@ -285,18 +715,74 @@ impl<'parser> Parser<'parser> {
         }))
     }
 
+    fn parse_try_expression(&mut self) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        if !self.expect_peek(Token::Lbrace) {
+            return None
+        }
+        let block = self.parse_block_statement();
+
+        let mut catch = None;
+        if self.peek_token_is(Token::Catch) {
+            self.next_token();
+            let checkpoint = self.checkpoint();
+            if !self.expect_peek(Token::Lparen) {
+                return None
+            }
+            if !matches!(self.peek_token, Some(Token::Ident(_))) {
+                self.peek_error(Token::Ident(String::from("identifier")));
+                return None
+            }
+            self.next_token();
+            let parameter = Identifier::new(self.cur_token.clone()?, self.cur_span);
+            if !self.expect_peek(Token::Rparen) {
+                return None
+            }
+            if !self.expect_peek(Token::Lbrace) {
+                return None
+            }
+            // Like the evaluator, the caught error gets a scope of its own.
+            self.scopes.push(HashMap::from([(parameter.value.clone(), None)]));
+            let body = self.parse_block_statement();
+            self.scopes.pop();
+            catch = Some(CatchClause { parameter, body });
+            self.wrap(checkpoint, SyntaxKind::Catch);
+        }
+
+        let mut finally = None;
+        if self.peek_token_is(Token::Finally) {
+            self.next_token();
+            let checkpoint = self.checkpoint();
+            if !self.expect_peek(Token::Lbrace) {
+                return None
+            }
+            finally = Some(self.parse_block_statement());
+            self.wrap(checkpoint, SyntaxKind::Finally);
+        }
+
+        if catch.is_none() && finally.is_none() {
+            let msg = format!(
+                "expected catch or finally after try block, got {:?} instead",
+                self.peek_token.as_ref()?.get_literal()
+            );
+            self.error(msg, self.peek_span);
+            return None
+        }
+
+        Some(Box::new(TryExpression { token, block, catch, finally }))
+    }
+
     fn parse_block_statement(&mut self) -> BlockStatement {
         let token = self.cur_token.clone().unwrap();
+        let checkpoint = self.checkpoint();
         let mut statements: Vec<Statement> = vec![];
         self.next_token();
 
         while !self.cur_token_is(Token::Rbrace) && !self.cur_token_is(Token::Eof) {
-            let stmt = self.parse_statement();
-            if let Some(stmt) = stmt {
-                statements.push(stmt);
-            }
+            statements.extend(self.parse_statement_or_recover());
             self.next_token();
         }
+        self.wrap(checkpoint, SyntaxKind::Block);
 
         BlockStatement{
             token,
@@ -305,6 +791,7 @@ impl<'parser> Parser<'parser> {
     }
 
     fn parse_function_literal(&mut self) -> Option<Box<dyn Expression>> {
+        let name = self.let_name.take();
         if !self.expect_peek(Token::Lparen) {
             return None
         }
@@ -314,12 +801,19 @@ impl<'parser> Parser<'parser> {
             return None
         }
 
-        let body = self.parse_block_statement();
+        // A function body starts a fresh loop context: `break` inside a
+        // closure must not target a loop surrounding the closure.
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        self.scopes.push(parameters.iter().map(|param| (param.value.clone(), None)).collect());
+        let body = Rc::new(self.parse_block_statement());
+        self.scopes.pop();
+        self.loop_depth = loop_depth;
 
         Some(Box::new(FunctionLiteral{
             token: self.cur_token.clone().unwrap(),
             parameters,
-            body
+            body,
+            name,
         }))
     }
 
@@ -332,11 +826,7 @@ impl<'parser> Parser<'parser> {
         }
 
         self.next_token();
-        let cur_token = self.cur_token.clone().unwrap();
-        let ident = Identifier{
-            value: cur_token.get_literal(),
-            token: cur_token
-        };
+        let ident = Identifier::new(self.cur_token.clone().unwrap(), self.cur_span);
 
         identifier.push(ident);
 
@@ -344,10 +834,7 @@ impl<'parser> Parser<'parser> {
             self.next_token();
             self.next_token();
 
-            let ident = Identifier{
-                token: self.cur_token.clone().unwrap(),
-                value: self.cur_token.clone().unwrap().get_literal()
-            };
+            let ident = Identifier::new(self.cur_token.clone().unwrap(), self.cur_span);
 
             identifier.push(ident);
         }
@@ -360,58 +847,103 @@ impl<'parser> Parser<'parser> {
     }
 
     fn parse_call_expression(&mut self, function: Box<dyn Expression>) -> Option<Box<CallExpression>> {
+        let span = self.cur_span;
         let arguments = self.parse_call_arguments();
         Some(Box::new(CallExpression{
             token: self.cur_token.clone()?,
             arguments: arguments?,
-            function: Some(function)
+            function,
+            span,
         }))
     } 
 
     fn parse_call_arguments(&mut self) -> Option<Vec<Box<dyn Expression>>> {
+        self.parse_expression_list(Token::Rparen)
+    }
+
+    fn parse_expression_list(&mut self, end: Token) -> Option<Vec<Box<dyn Expression>>> {
         let mut args = vec![];
-        if self.peek_token_is(Token::Rparen) {
+        if self.peek_token_is(end.clone()) {
             self.next_token();
             return Some(args);
         }
 
-        self.next_token();
-        let exp = self.parse_expression(Lowest).expect("expression is None");
-
-        args.push(exp);
+        args.push(self.parse_operand(Lowest));
 
         while self.peek_token_is(Token::Comma){
             self.next_token();
-            self.next_token();
-            if let Some(exp) = self.parse_expression(Lowest){
-                args.push(exp);
-            }
+            args.push(self.parse_operand(Lowest));
         }
 
-        if !self.expect_peek(Token::Rparen){
+        if !self.expect_peek(end){
             return None;
         } 
 
         Some(args)
     }
 
-    fn parse_grouped_expression(&mut self) -> Option<Box<dyn Expression>> {
+    fn parse_array_literal(&mut self) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        let elements = self.parse_expression_list(Token::Rbracket)?;
+        Some(Box::new(ArrayLiteral { token, elements }))
+    }
+
+    fn parse_index_expression(&mut self, left: Box<dyn Expression>) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        let span = self.cur_span;
+        let index = self.parse_operand(Lowest);
+
+        if !self.expect_peek(Token::Rbracket) {
+            return None
+        }
+
+        Some(Box::new(IndexExpression { token, left, index, span }))
+    }
+
+    fn parse_member_expression(&mut self, left: Box<dyn Expression>) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        let span = self.cur_span;
+        let Some(Token::Ident(_)) = self.peek_token else {
+            let msg = format!("expected member name after ., got {:?} instead", self.peek_token.as_ref()?.get_literal());
+            self.error(msg, self.peek_span);
+            return None;
+        };
+        self.next_token();
+        let property = Identifier::new(self.cur_token.clone()?, self.cur_span);
+        Some(Box::new(MemberExpression { token, left, property, span }))
+    }
+
+    // The path must be a string literal so imports can be found without
+    // evaluating the program.
+    fn parse_import_expression(&mut self) -> Option<Box<dyn Expression>> {
+        let token = self.cur_token.clone()?;
+        let span = self.cur_span;
+        let Some(Token::String(path)) = self.peek_token.clone() else {
+            let msg = format!("expected import path to be a string, got {:?} instead", self.peek_token.as_ref()?.get_literal());
+            self.error(msg, self.peek_span);
+            return None;
+        };
         self.next_token();
+        Some(Box::new(ImportExpression { token, path, span }))
+    }
 
-        let exp = self.parse_expression(Precedence::Lowest);
+    fn parse_grouped_expression(&mut self) -> Option<Box<dyn Expression>> {
+        let exp = self.parse_operand(Lowest);
 
         if !self.expect_peek(Token::Rparen) {
             return None
         }
 
-        exp
+        Some(exp)
     }
 
     fn parse_expression_statement(&mut self) -> Option<Statement> {
-        let stmt = Statement::Expression {
-            token: self.cur_token.clone()?,
-            expression: self.parse_expression(Lowest),
+        let (token, start) = (self.cur_token.clone()?, self.cur_span);
+        let expression = match self.parse_expression(Lowest) {
+            Some(exp) => exp,
+            None => Box::new(ErrorExpression { token: token.clone(), span: Span { end: self.cur_span.end, ..start } }),
         };
+        let stmt = Statement::Expression { token, expression };
 
         if self.peek_token_is(Token::Semicolon) {
             self.next_token();
@@ -463,8 +995,9 @@ mod test {
 
     use std::{any::Any, ops::Deref};
     use super::Parser;
-    use crate::ast::FunctionLiteral;
+    use crate::ast::{ArrayLiteral, AssignExpression, FloatLiteral, FunctionLiteral, ImportExpression, IndexExpression, MemberExpression, MissingExpression, StringLiteral, TryExpression};
     use crate::lexer::Lexer;
+    use crate::token::Token;
     use crate::ast::{Boolean, Expression, Identifier, InfixExpression, IntegerLiteral, Node, PrefixExpression, Statement};
 
     #[test]
@@ -543,8 +1076,8 @@ return 993322;";
             name,
             value: _,
         } = statement {
-            let val = &name.as_ref().unwrap().value;
-            let token_literal = &name.as_ref().unwrap().token_literal();
+            let val = &name.value;
+            let token_literal = &name.token_literal();
 
             if val != var_name {
                 eprintln!("let statement value is not {var_name}, got {}", val);
@@ -578,9 +1111,7 @@ return 993322;";
                     token: _,
                     expression,
                 } => {
-                    if let Some(exp) = expression{
-                        self::test_literal_expression(exp.deref(), &String::from("foobar"));
-                    }
+                    self::test_literal_expression(expression.deref(), &String::from("foobar"));
                 }
                 _ => panic!("Statement received is not type Statement::Expression"),
             }
@@ -602,12 +1133,7 @@ return 993322;";
                     token: _,
                     expression,
                 } => {
-                    match expression {
-                        Some(exp) => {
-                            self::test_boolean_literal(exp.deref(), true);
-                        },
-                        None => panic!("expression is None")
-                    }
+                    self::test_boolean_literal(expression.deref(), true);
                 }
                 _ => panic!("Statement received is not type Statement::Expression"),
             }
@@ -629,9 +1155,7 @@ return 993322;";
                     token: _,
                     expression,
                 } => {
-                    if let Some(exp) = expression {
-                        self::test_literal_expression(exp.deref(), &238784);
-                    }
+                    self::test_literal_expression(expression.deref(), &238784);
                 }
                 _ => panic!("Statement received is not type Statement::Expression"),
             }
@@ -667,6 +1191,11 @@ return 993322;";
                 operator: "!",
                 value: &false,
             },
+            Input {
+                input: "~5",
+                operator: "~",
+                value: &5,
+            },
         ];
 
         for test_case in input {
@@ -685,27 +1214,22 @@ return 993322;";
                     Statement::Expression {
                         token: _,
                         expression,
-                    } => match expression {
-                        Some(expression) => {
-                            match expression
-                                .as_ref()
-                                .as_any()
-                                .downcast_ref::<PrefixExpression>()
-                            {
-                                Some(exp) => {
-                                    assert_eq!(exp.operator, test_case.operator);
-                                    self::test_literal_expression(exp.right.deref(), &test_case.value);
-                                    if !test_literal_expression(
-                                        exp.right.as_ref(),
-                                        test_case.value,
-                                    ) {
-                                        return;
-                                    }
-                                }
-                                None => panic!("Expression is not of type PrefixExpression"),
+                    } => match expression
+                        .as_ref()
+                        .as_any()
+                        .downcast_ref::<PrefixExpression>()
+                    {
+                        Some(exp) => {
+                            assert_eq!(exp.operator, test_case.operator);
+                            self::test_literal_expression(exp.right.deref(), &test_case.value);
+                            if !test_literal_expression(
+                                exp.right.as_ref(),
+                                test_case.value,
+                            ) {
+                                return;
                             }
                         }
-                        None => panic!("Expression is None"),
+                        None => panic!("Expression is not of type PrefixExpression"),
                     },
                     _ => panic!("Statement is not of type Statement::Expression"),
                 }
@@ -713,7 +1237,7 @@ return 993322;";
         }
     }
 
-    fn test_integer_literal(il: &dyn Expression, value: i32) -> bool {
+    fn test_integer_literal(il: &dyn Expression, value: i64) -> bool {
         match il.as_any().downcast_ref::<IntegerLiteral>() {
             Some(integ) => {
                 assert_eq!(integ.value, value);
@@ -761,6 +1285,17 @@ return 993322;";
             Input::new("true == true", &true, "==", &true),
             Input::new("true != false", &true, "!=", &false),
             Input::new("false == false", &false, "==", &false),
+            Input::new("5 <= 5", &5, "<=", &5),
+            Input::new("5 >= 5", &5, ">=", &5),
+            Input::new("5 % 5", &5, "%", &5),
+            Input::new("true && false", &true, "&&", &false),
+            Input::new("true || false", &true, "||", &false),
+            Input::new("5 & 5", &5, "&", &5),
+            Input::new("5 | 5", &5, "|", &5),
+            Input::new("5 ^ 5", &5, "^", &5),
+            Input::new("5 << 5", &5, "<<", &5),
+            Input::new("5 >> 5", &5, ">>", &5),
+            Input::new("5 ** 5", &5, "**", &5),
         ];
 
         for test_case in input {
@@ -779,13 +1314,10 @@ return 993322;";
                     Statement::Expression {
                         token: _,
                         expression,
-                    } => match expression {
-                        Some(expression) => {
-                            if !test_infix_expression(expression.deref(), test_case.left_value, test_case.operator, test_case.right_value){
-                                return
-                            }
+                    } => {
+                        if !test_infix_expression(expression.deref(), test_case.left_value, test_case.operator, test_case.right_value){
+                            return
                         }
-                        None => panic!("Expression is None"),
                     },
                     _ => panic!("Statement is not of type Statement::Expression"),
                 }
@@ -806,9 +1338,9 @@ return 993322;";
 
     fn test_literal_expression(exp: &dyn Expression, expected: &dyn Any) -> bool {
         if let Some(v) = expected.downcast_ref::<i32>() {
-            return test_integer_literal( exp, *v);
+            return test_integer_literal( exp, *v as i64);
         } else if let Some(v) = expected.downcast_ref::<i64>() {
-            return test_integer_literal( exp, *v as i32);
+            return test_integer_literal( exp, *v);
         } else if let Some(v) = expected.downcast_ref::<String>() {
             return test_identifier(exp, v);
         } else if let Some(v) = expected.downcast_ref::<bool>() {
@@ -879,6 +1411,37 @@ return 993322;";
             Input::new("add(a + b + c * d / f + g)","add((((a + b) + ((c * d) / f)) + g), )"),
             Input::new("add(a, b, add(b, c))","add(a, b, add(b, c, ), )"),
             //Input::new("add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))","add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8), ), )"),
+            Input::new("a <= b == c >= d", "((a <= b) == (c >= d))"),
+            Input::new("a + b % c", "(a + (b % c))"),
+            Input::new("a || b && c", "(a || (b && c))"),
+            Input::new("a && b || c && d", "((a && b) || (c && d))"),
+            Input::new("a == b && c != d", "((a == b) && (c != d))"),
+            Input::new("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            Input::new("a & b == c", "(a & (b == c))"),
+            Input::new("a || b | c", "(a || (b | c))"),
+            Input::new("a << b + c", "(a << (b + c))"),
+            Input::new("a < b << c", "(a < (b << c))"),
+            Input::new("a >> b >> c", "((a >> b) >> c)"),
+            Input::new("~a & b", "((~a) & b)"),
+            Input::new("a ** b ** c", "(a ** (b ** c))"),
+            Input::new("a * b ** c", "(a * (b ** c))"),
+            Input::new("-a ** b", "(-(a ** b))"),
+            Input::new("a ** -b", "(a ** (-b))"),
+            Input::new("(a + b) * c", "((a + b) * c)"),
+            Input::new("2 ** (3 ** 2) ** 2", "(2 ** ((3 ** 2) ** 2))"),
+            Input::new("x = 5", "(x = 5)"),
+            Input::new("x = y = 5", "(x = (y = 5))"),
+            Input::new("x = a + b * c", "(x = (a + (b * c)))"),
+            Input::new("x += 1", "(x += 1)"),
+            Input::new("x -= a || b", "(x -= (a || b))"),
+            Input::new("x *= y /= 2", "(x *= (y /= 2))"),
+            Input::new("a * [1, 2, 3, 4][b * c] * d", "((a * ([1, 2, 3, 4][(b * c)])) * d)"),
+            Input::new("add(a * b[2], b[1], 2 * [1, 2][1])", "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])), )"),
+            Input::new("a[0] = b[1] + 1", "((a[0]) = ((b[1]) + 1))"),
+            Input::new("-a[0] ** 2", "(-((a[0]) ** 2))"),
+            Input::new("-m.x ** 2", "(-((m.x) ** 2))"),
+            Input::new("a.b.c[0] + m.f(1)", "((((a.b).c)[0]) + (m.f)(1, ))"),
+            Input::new("(import \"lib.mk\").x", "(import \"lib.mk\".x)"),
         ];
 
         for tc in tests {
@@ -886,16 +1449,11 @@ return 993322;";
             let mut p = Parser::new(&mut l);
             let program = p.parse_program();
 
-            println!("{:?}", program.statements);
             assert_eq!(program.statements.len(), 1, "program.statements.len() is not 1. got {}.\n\n DUMP:\n{:?}", program.statements.len(), program.statements);
             if let Some(stmt) = program.statements.first() {
                 match stmt {
                     Statement::Expression { token: _, expression } => {
-                        if let Some(exp) = expression {
-                            assert_eq!(format!("{}", exp), format!("{}", tc.expected), "exp is not {}. got {}", tc.expected, exp);
-                        } else {
-                            panic!("expression is none")
-                        }
+                        assert_eq!(format!("{}", expression), format!("{}", tc.expected), "exp is not {}. got {}", tc.expected, expression);
                     },
                     _ => panic!("stmt is not of type Statement::Expression")
                 }
@@ -915,27 +1473,22 @@ return 993322;";
 
         match program.statements.first().unwrap() {
             Statement::Expression { token: _, expression } => {
-                match expression {
-                    Some(exp) => {
-                        match exp.as_any().downcast_ref::<FunctionLiteral>() {
-                            Some(fn_ltrl) => {
-                                assert_eq!(fn_ltrl.parameters.len(), 2, "fn_ltrl.parameters is not 2. got {}", fn_ltrl.parameters.len());
-                                test_literal_expression(&fn_ltrl.parameters[0], &String::from("x"));
-                                test_literal_expression(&fn_ltrl.parameters[1], &String::from("y"));
-
-                                assert_eq!(fn_ltrl.body.statements.len(), 1, "fn_ltrl.body.statements is not 1. got {}", fn_ltrl.body.statements.len());
-
-                                match &fn_ltrl.body.statements[0] {
-                                    Statement::Expression { token: _, expression } => {
-                                        test_infix_expression(expression.as_ref().unwrap().deref(), &String::from("x"), "+", &String::from("y"));
-                                    },
-                                    _ => panic!("function body statement is not Expression")
-                                }
+                match expression.as_any().downcast_ref::<FunctionLiteral>() {
+                    Some(fn_ltrl) => {
+                        assert_eq!(fn_ltrl.parameters.len(), 2, "fn_ltrl.parameters is not 2. got {}", fn_ltrl.parameters.len());
+                        test_literal_expression(&fn_ltrl.parameters[0], &String::from("x"));
+                        test_literal_expression(&fn_ltrl.parameters[1], &String::from("y"));
+
+                        assert_eq!(fn_ltrl.body.statements.len(), 1, "fn_ltrl.body.statements is not 1. got {}", fn_ltrl.body.statements.len());
+
+                        match &fn_ltrl.body.statements[0] {
+                            Statement::Expression { token: _, expression } => {
+                                test_infix_expression(expression.deref(), &String::from("x"), "+", &String::from("y"));
                             },
-                            None => panic!("expression cannot be casted to a FunctionLiteral")
+                            _ => panic!("function body statement is not Expression")
                         }
                     },
-                    None => panic!("expression is None")
+                    None => panic!("expression cannot be casted to a FunctionLiteral")
                 }
             },
             _ => panic!("Statement is not of type Expression")
@@ -977,30 +1530,503 @@ return 993322;";
 
             match program.statements.first().unwrap() {
                 Statement::Expression { token: _, expression } => {
-                    match expression {
-                        Some(exp) => {
-                            match exp.as_any().downcast_ref::<FunctionLiteral>() {
-                                Some(fn_ltrl) => {
-                                    assert_eq!(fn_ltrl.parameters.len(), test.expected.len(), "fn_ltrl.parameters is not {}. got {}", test.expected.len(), fn_ltrl.parameters.len());
+                    match expression.as_any().downcast_ref::<FunctionLiteral>() {
+                        Some(fn_ltrl) => {
+                            assert_eq!(fn_ltrl.parameters.len(), test.expected.len(), "fn_ltrl.parameters is not {}. got {}", test.expected.len(), fn_ltrl.parameters.len());
 
 
-                                    for (i, ident) in test.expected.iter().enumerate() {
-                                        if !test.expected.is_empty(){
-                                            println!("{:?}", fn_ltrl.parameters);
-                                            println!("{:?}", &String::from(*ident));
-
-                                            test_literal_expression(&fn_ltrl.parameters[i], &String::from(*ident));
-                                        }
-                                    }
-                                },
-                                None => panic!("expression cannot be casted to a FunctionLiteral")
+                            for (i, ident) in test.expected.iter().enumerate() {
+                                if !test.expected.is_empty(){
+                                    test_literal_expression(&fn_ltrl.parameters[i], &String::from(*ident));
+                                }
                             }
                         },
-                        None => panic!("expression is None")
+                        None => panic!("expression cannot be casted to a FunctionLiteral")
                     }
                 },
                 _ => panic!("Statement is not of type Expression")
             }
         }
     }
+
+    #[test]
+    fn test_while_statement(){
+        let input = "while (x < 10) { x; break; continue; }";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        assert_eq!(program.statements.len(), 1, "program.statements is not 1. got {}", program.statements.len());
+
+        match program.statements.first().unwrap() {
+            Statement::While { token: _, condition, body } => {
+                test_infix_expression(condition.deref(), &String::from("x"), "<", &10);
+                assert_eq!(body.statements.len(), 3, "body.statements is not 3. got {}", body.statements.len());
+                assert!(matches!(body.statements[1], Statement::Break { .. }), "body.statements[1] is not Break");
+                assert!(matches!(body.statements[2], Statement::Continue { .. }), "body.statements[2] is not Continue");
+            },
+            _ => panic!("Statement is not of type While")
+        }
+    }
+
+    #[test]
+    fn test_for_statement(){
+        let input = "for (item in items) { item; }";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        assert_eq!(program.statements.len(), 1, "program.statements is not 1. got {}", program.statements.len());
+
+        match program.statements.first().unwrap() {
+            Statement::For { token: _, variable, iterable, body } => {
+                test_identifier(variable, "item");
+                test_identifier(iterable.deref(), "items");
+                assert_eq!(body.statements.len(), 1, "body.statements is not 1. got {}", body.statements.len());
+            },
+            _ => panic!("Statement is not of type For")
+        }
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop(){
+        let tests = [
+            ("break;", "break statement outside of a loop"),
+            ("continue;", "continue statement outside of a loop"),
+            ("if (x) { break; }", "break statement outside of a loop"),
+            ("while (x) { fn() { continue; }; }", "continue statement outside of a loop"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            assert_eq!(p.errors().len(), 1, "expected 1 error for {}. got {:?}", input, p.errors());
+            assert_eq!(p.errors()[0], expected);
+        }
+    }
+
+    #[test]
+    fn test_assign_expression(){
+        let input = "x += 5;";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        assert_eq!(program.statements.len(), 1, "program.statements is not 1. got {}", program.statements.len());
+
+        match program.statements.first().unwrap() {
+            Statement::Expression { token: _, expression } => {
+                match expression.as_any().downcast_ref::<AssignExpression>() {
+                    Some(assign) => {
+                        test_identifier(assign.target.deref(), "x");
+                        assert_eq!(assign.operator, "+=", "assign.operator is not +=. got {}", assign.operator);
+                        test_literal_expression(assign.value.deref(), &5);
+                    },
+                    None => panic!("expression cannot be casted to an AssignExpression")
+                }
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_invalid_assignment_target(){
+        let tests = [
+            ("5 = x;", "invalid assignment target: 5"),
+            ("a + b = c;", "invalid assignment target: (a + b)"),
+            ("-x += 1;", "invalid assignment target: (-x)"),
+            ("m.x = 1;", "invalid assignment target: (m.x)"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            assert!(!p.errors().is_empty(), "expected an error for {}", input);
+            assert_eq!(p.errors()[0], expected);
+        }
+    }
+
+    #[test]
+    fn test_const_statements(){
+        let input = "const x = 5;
+const y = fn(x) { x = 1; let y = 2; y += 1; };";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        assert_eq!(program.statements.len(), 2, "program.statements is not 2. got {}", program.statements.len());
+        for (stmt, name) in program.statements.iter().zip(["x", "y"]) {
+            assert_eq!(stmt.token_literal(), "const", "stmt.token_literal() is not const. got {}", stmt.token_literal());
+            assert!(test_let_statement(stmt, name));
+        }
+    }
+
+    #[test]
+    fn test_const_violations(){
+        let tests = [
+            ("const x = 1;\nx = 2;", "cannot assign to constant x at 2:1 (declared at 1:7)"),
+            ("const x = 1; x += 2;", "cannot assign to constant x at 1:14 (declared at 1:7)"),
+            ("const x = 1;\nlet x = 2;", "cannot redeclare constant x at 2:5 (declared at 1:7)"),
+            ("const x = 1; const x = 2;", "cannot redeclare constant x at 1:20 (declared at 1:7)"),
+            ("const x = 1; let f = fn() { x = 2; };", "cannot assign to constant x at 1:29 (declared at 1:7)"),
+            ("const x = 1; while (true) { x = 2; }", "cannot assign to constant x at 1:29 (declared at 1:7)"),
+            ("const x = 1; try { 1 } catch (e) { x = 2; }", "cannot assign to constant x at 1:36 (declared at 1:7)"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            assert_eq!(p.errors().len(), 1, "expected 1 error for {}. got {:?}", input, p.errors());
+            assert_eq!(p.errors()[0], expected);
+        }
+
+        // A caught error is bound in the catch block only.
+        let allowed = [
+            "const e = 1; try { throw 2; } catch (e) { puts(e); }",
+            "const e = 1; try { throw 2; } catch (e) { e = 3; }",
+            "try { throw 2; } catch (e) { const f = e; } const f = 1;",
+        ];
+        for input in allowed {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+            check_parser_errors(&p);
+        }
+    }
+
+    #[test]
+    fn test_string_literal_expression(){
+        let input = "\"hello world\";";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match program.statements.first().unwrap() {
+            Statement::Expression { token: _, expression } => {
+                match expression.as_any().downcast_ref::<StringLiteral>() {
+                    Some(string) => assert_eq!(string.value, "hello world", "string.value is not hello world. got {}", string.value),
+                    None => panic!("expression cannot be casted to a StringLiteral")
+                }
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_array_literal_parsing(){
+        let input = "[1, 2 * 2, 3 + 3]";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match program.statements.first().unwrap() {
+            Statement::Expression { token: _, expression } => {
+                match expression.as_any().downcast_ref::<ArrayLiteral>() {
+                    Some(array) => {
+                        assert_eq!(array.elements.len(), 3, "array.elements is not 3. got {}", array.elements.len());
+                        test_integer_literal(array.elements[0].deref(), 1);
+                        test_infix_expression(array.elements[1].deref(), &2, "*", &2);
+                        test_infix_expression(array.elements[2].deref(), &3, "+", &3);
+                    },
+                    None => panic!("expression cannot be casted to an ArrayLiteral")
+                }
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_index_expression_parsing(){
+        let input = "myArray[1 + 1]";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match program.statements.first().unwrap() {
+            Statement::Expression { token: _, expression } => {
+                match expression.as_any().downcast_ref::<IndexExpression>() {
+                    Some(index) => {
+                        test_identifier(index.left.deref(), "myArray");
+                        test_infix_expression(index.index.deref(), &1, "+", &1);
+                    },
+                    None => panic!("expression cannot be casted to an IndexExpression")
+                }
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_member_expression_parsing(){
+        let input = "math.square";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match program.statements.first().unwrap() {
+            Statement::Expression { token: _, expression } => {
+                match expression.as_any().downcast_ref::<MemberExpression>() {
+                    Some(member) => {
+                        test_identifier(member.left.deref(), "math");
+                        test_identifier(&member.property, "square");
+                    },
+                    None => panic!("expression cannot be casted to a MemberExpression")
+                }
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_import_expression_parsing(){
+        let input = "let math = import \"lib/math.mk\";";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match program.statements.first().unwrap() {
+            Statement::Let { value, .. } => {
+                match value.as_any().downcast_ref::<ImportExpression>() {
+                    Some(import) => assert_eq!(import.path, "lib/math.mk", "import.path is not lib/math.mk. got {}", import.path),
+                    None => panic!("expression cannot be casted to an ImportExpression")
+                }
+            },
+            _ => panic!("Statement is not of type Let")
+        }
+    }
+
+    #[test]
+    fn test_function_literal_names(){
+        let input = "let add = fn(x) { fn(y) { x + y } }; let f = add; fn() {};";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match &program.statements[0] {
+            Statement::Let { value, .. } => {
+                let function = value.as_any().downcast_ref::<FunctionLiteral>().expect("expression cannot be casted to a FunctionLiteral");
+                assert_eq!(function.name.as_deref(), Some("add"));
+                let Statement::Expression { expression: inner, .. } = &function.body.statements[0] else { panic!("Statement is not of type Expression") };
+                let inner = inner.as_any().downcast_ref::<FunctionLiteral>().expect("expression cannot be casted to a FunctionLiteral");
+                assert_eq!(inner.name, None);
+            },
+            _ => panic!("Statement is not of type Let")
+        }
+        match &program.statements[2] {
+            Statement::Expression { expression: exp, .. } => {
+                let function = exp.as_any().downcast_ref::<FunctionLiteral>().expect("expression cannot be casted to a FunctionLiteral");
+                assert_eq!(function.name, None);
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_try_expression_parsing(){
+        let tests = [
+            ("try { x } catch (e) { e }", "try x catch (e) e"),
+            ("try { x } finally { y }", "try x finally y"),
+            ("let v = try { a } catch (err) { 0 } finally { b };", "let v = try a catch (err) 0 finally b;"),
+            ("throw \"boom\";", "throw \"boom\";"),
+            ("throw a + b", "throw (a + b);"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+            assert_eq!(program.to_string(), expected, "wrong program for {}", input);
+        }
+
+        let mut l = Lexer::new("try { x } catch (e) { e }");
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        match program.statements.first().unwrap() {
+            Statement::Expression { expression: exp, .. } => {
+                let try_exp = exp.as_any().downcast_ref::<TryExpression>().expect("expression cannot be casted to a TryExpression");
+                assert_eq!(try_exp.catch.as_ref().unwrap().parameter.value, "e");
+                assert!(try_exp.finally.is_none());
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_try_expression_errors(){
+        let tests = [
+            ("try { x };", "expected catch or finally after try block, got \";\" instead"),
+            ("try { x } catch { y }", "expected next token to be (, got \"{\" instead"),
+            ("try { x } catch (1) { y }", "expected next token to be identifier, got \"1\" instead"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            assert!(!p.errors().is_empty(), "expected an error for {}", input);
+            assert_eq!(p.errors()[0], expected);
+        }
+    }
+
+    #[test]
+    fn test_import_and_member_errors(){
+        let tests = [
+            ("import x;", "expected import path to be a string, got \"x\" instead"),
+            ("m.1;", "expected member name after ., got \"1\" instead"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            assert!(!p.errors().is_empty(), "expected an error for {}", input);
+            assert_eq!(p.errors()[0], expected);
+        }
+    }
+
+    #[test]
+    fn test_error_spans(){
+        let tests = [
+            ("let x 5;", (6, 7)),
+            ("1 +\n  ;", (6, 7)),
+            ("const c = 1; c = 2;", (13, 14)),
+            ("5 = 1;", (2, 3)),
+        ];
+
+        for (input, (start, end)) in tests {
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            assert_eq!(p.errors().len(), p.error_spans().len(), "one span per error for {}", input);
+            let span = p.error_spans()[0];
+            assert_eq!((span.start, span.end), (start, end), "wrong span for {:?} in {}", p.errors()[0], input);
+        }
+    }
+
+    #[test]
+    fn test_broken_input_still_parses(){
+        let input = "let x = ;\nlet = 2;\nputs(1, );\nlet y = (1 + ;\nlet z = 3;";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+
+        assert_eq!(p.errors().len(), 5, "wrong errors {:?}", p.errors());
+        let statements: Vec<String> = program.statements.iter().map(|stmt| stmt.to_string()).collect();
+        assert_eq!(statements, ["let x = <missing>;", "<error>", "puts(1, <missing>, )", "let y = <error>;", "let z = 3;"]);
+
+        match &program.statements[0] {
+            Statement::Let { value, .. } => {
+                let missing = value.as_any().downcast_ref::<MissingExpression>().expect("value is not missing");
+                assert_eq!((missing.span.start, missing.span.end), (8, 8));
+            },
+            stmt => panic!("statement is not a let. got={}", stmt),
+        }
+        match &program.statements[1] {
+            Statement::Error { token, span } => {
+                assert_eq!(*token, Token::Let);
+                assert_eq!((span.start, span.end), (10, 18));
+            },
+            stmt => panic!("statement is not an error. got={}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_broken_statements_in_blocks(){
+        let input = "fn() { let = 1; let a = 2; a }";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+
+        assert_eq!(p.errors().len(), 1);
+        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.to_string(), "fn( )\n<error>let a = 2;a");
+    }
+
+    #[test]
+    fn test_float_literal_expression(){
+        let input = "3.25;";
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        match program.statements.first().unwrap() {
+            Statement::Expression { token: _, expression } => {
+                match expression.as_any().downcast_ref::<FloatLiteral>() {
+                    Some(float) => {
+                        assert_eq!(float.value, 3.25, "float.value is not 3.25. got {}", float.value);
+                        assert_eq!(float.token_literal(), "3.25", "float.token_literal() is not 3.25. got {}", float.token_literal());
+                    },
+                    None => panic!("expression cannot be casted to a FloatLiteral")
+                }
+            },
+            _ => panic!("Statement is not of type Expression")
+        }
+    }
+
+    #[test]
+    fn test_integer_literal_range(){
+        let mut l = Lexer::new("9223372036854775807;");
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.to_string(), "9223372036854775807");
+
+        let mut l = Lexer::new("9223372036854775808;");
+        let mut p = Parser::new(&mut l);
+        p.parse_program();
+        assert_eq!(p.errors(), &["could not parse 9223372036854775808 as integer"]);
+    }
+
+    #[test]
+    fn test_deep_nesting(){
+        let deep = [
+            format!("{}1{};", "(".repeat(20000), ")".repeat(20000)),
+            format!("{}1{};", "[".repeat(20000), "]".repeat(20000)),
+            format!("{}1;", "-".repeat(20000)),
+            format!("{}1{}", "if (true) { ".repeat(20000), " }".repeat(20000)),
+            format!("{}1{}", "while (true) { ".repeat(20000), " }".repeat(20000)),
+        ];
+        for input in deep {
+            let mut l = Lexer::new(&input);
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+            assert_eq!(p.errors(), &["code is nested more than 256 levels deep"], "wrong errors for {}...", &input[..20]);
+        }
+
+        // The statement is one level, and each pair of parentheses another.
+        let fits = format!("{}1{}; let x = 2;", "(".repeat(254), ")".repeat(254));
+        let mut l = Lexer::new(&fits);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.statements.len(), 2);
+
+        let too_deep = format!("{}1{}; let x = 2;", "(".repeat(255), ")".repeat(255));
+        let mut l = Lexer::new(&too_deep);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        assert_eq!(p.errors(), &["code is nested more than 256 levels deep"]);
+        assert_eq!(program.statements.last().unwrap().to_string(), "let x = 2;");
+    }
 }
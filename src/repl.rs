@@ -1,9 +1,27 @@
-use std::io::{stdin, stdout, Write};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
 
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use interpreter::ast::{Program, Statement};
+use interpreter::evaluator::Evaluator;
+use interpreter::highlight;
+use interpreter::lexer::Lexer;
+use interpreter::object::{Env, Environment, Object};
+use interpreter::parser::Parser;
+use interpreter::token::{Token, KEYWORDS};
+use interpreter::resolver::Resolver;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
 
 const PROMPT: &str = ">>";
+const CONTINUATION_PROMPT: &str = "..";
 const MONKEY_FACE: &str = " __,__
                 .--. .-\" \"-. .--.
             / .. \\/ .-. .-. \\/ .. 
@@ -15,31 +33,439 @@ const MONKEY_FACE: &str = " __,__
                 \\ \\ '~' / /
                 '._ '-=-' _.'
                     '-----";
-pub fn start(){
+const HELP: &str = ":help            show this help
+:ast <code>      print how <code> parses
+:tokens <code>   print the tokens of <code> with their positions
+:env             list the bindings of the session
+:load <file>     evaluate a file in the session
+:reset           drop every binding
+:time            toggle printing how long each evaluation takes
+:mode <mode>     what plain input does: eval (the default), ast or tokens";
 
-    loop {
-        let mut line = String::new();
-        print!("{PROMPT} ");
-        stdout().flush().unwrap();
-        if stdin().read_line(&mut line).unwrap() == 0 {
+type LineEditor = Editor<MonkeyHelper, DefaultHistory>;
+
+// What the REPL does with input that is not a command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Eval,
+    Ast,
+    Tokens,
+}
+
+// Every input is evaluated in the same global environment, so bindings made
+// by earlier lines stay visible until `:reset`.
+struct Session {
+    evaluator: Evaluator,
+    env: Env,
+    mode: Mode,
+    timing: bool,
+}
+
+pub fn start(evaluator: Evaluator){
+    let mut editor = match LineEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("could not start the line editor: {err}");
+            return;
+        }
+    };
+    let mut session = Session::new(evaluator);
+    editor.set_helper(Some(MonkeyHelper { builtins: session.evaluator.builtin_names(), env: Rc::clone(&session.env) }));
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history file before the first session ends.
+        let _ = editor.load_history(path);
+    }
+
+    while let Some(input) = read_input(&mut editor) {
+        match input.trim_start().strip_prefix(':') {
+            Some(command) => session.run_command(command),
+            None => match session.mode {
+                Mode::Eval => session.eval(&input, None),
+                Mode::Ast => print_ast(&input),
+                Mode::Tokens => print!("{}", format_tokens(&input)),
+            },
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.env = Rc::clone(&session.env);
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!("could not save history to {}: {err}", path.display());
+        }
+    }
+}
+
+impl Session {
+    fn new(evaluator: Evaluator) -> Self {
+        Self { evaluator, env: Environment::new(), mode: Mode::Eval, timing: false }
+    }
+
+    // Starts over with a new evaluator as well as a new environment, so
+    // modules are loaded again the next time they are imported. The
+    // capabilities and limits stay what they were.
+    fn reset(&mut self) {
+        let mut evaluator = Evaluator::with_capabilities(self.evaluator.capabilities().clone());
+        evaluator.set_limits(*self.evaluator.limits());
+        self.evaluator = evaluator;
+        self.env = Environment::new();
+    }
+
+    fn run_command(&mut self, command: &str) {
+        let (name, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let arg = arg.trim();
+        match (name.trim(), arg) {
+            ("help", _) => println!("{HELP}"),
+            ("ast", code) => print_ast(code),
+            ("tokens", code) => print!("{}", format_tokens(code)),
+            ("env", _) => print!("{}", format_env(&self.env)),
+            ("load", "") => println!("usage: :load <file>"),
+            ("load", path) => match fs::read_to_string(path) {
+                Ok(input) => self.eval(&input, Some(Path::new(path))),
+                Err(err) => println!("could not read {path}: {err}"),
+            },
+            ("reset", _) => self.reset(),
+            ("time", _) => {
+                self.timing = !self.timing;
+                println!("timing is {}", if self.timing { "on" } else { "off" });
+            },
+            ("mode", "eval") => self.mode = Mode::Eval,
+            ("mode", "ast") => self.mode = Mode::Ast,
+            ("mode", "tokens") => self.mode = Mode::Tokens,
+            ("mode", _) => println!("usage: :mode eval|ast|tokens"),
+            (name, _) => println!("unknown command :{name}, see :help"),
+        }
+    }
+
+    // Evaluates `input` in the session. Input read from a file resolves its
+    // imports relative to that file.
+    fn eval(&mut self, input: &str, path: Option<&Path>) {
+        let Some(program) = parse(input) else {
+            return;
+        };
+
+        let mut globals = self.evaluator.builtin_names();
+        globals.extend(self.env.borrow().names());
+        let mut r = Resolver::with_globals(&globals);
+        r.declare_constants(&self.env.borrow().constants());
+        r.resolve_program(&program);
+        for warning in r.warnings() {
+            println!("warning: {warning}");
+        }
+        if !r.errors().is_empty() {
+            print_parse_errors(&r.errors().iter().map(|e| e.to_string()).collect::<Vec<String>>());
             return;
         }
-        let mut l = Lexer::new(&line);
-        let mut p = Parser::new(&mut l);
-        let program = p.parse_program();
-        if !p.errors().is_empty() {
-            print_parse_errors(p.errors());
-            continue
+
+        // Only an input ending in an expression has a value worth echoing;
+        // `let` lines and loops print nothing unless they fail.
+        let echo = matches!(program.statements.last(), Some(Statement::Expression { .. } | Statement::Return { .. }));
+        let started = Instant::now();
+        let evaluated = match path {
+            Some(path) => self.evaluator.eval_file_program(&program, &self.env, path),
+            None => self.evaluator.eval_program(&program, &self.env),
+        };
+        let elapsed = started.elapsed();
+        match evaluated {
+            Object::Error(err) => println!("ERROR: {err}"),
+            evaluated if evaluated.is_error() || echo => println!("{evaluated}"),
+            _ => {}
+        }
+        if self.timing {
+            println!("time: {elapsed:?}");
+        }
+    }
+}
+
+fn parse(input: &str) -> Option<Program> {
+    let mut l = Lexer::new(input);
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+    if !p.errors().is_empty() {
+        print_parse_errors(p.errors());
+        return None;
+    }
+    Some(program)
+}
+
+fn print_ast(input: &str) {
+    if let Some(program) = parse(input) {
+        for stmt in program.statements.iter() {
+            println!("{stmt}");
+        }
+    }
+}
+
+// One token per line, preceded by where it starts.
+fn format_tokens(input: &str) -> String {
+    let mut l = Lexer::new(input);
+    let mut out = String::new();
+    loop {
+        let token = l.next_token();
+        if token == Token::Eof {
+            return out;
         }
+        out.push_str(&format!("{}\t{:?}\n", l.span(), token));
+    }
+}
 
-        println!("{}", program.statements.iter().map(|stmt| stmt.to_string()).collect::<Vec<String>>().join("\n"));
+// A value as listed by `:env`. Functions are shown by their parameters
+// only, since their bodies can be long.
+pub fn describe(value: &Object) -> String {
+    match value {
+        Object::Function { parameters, .. } => {
+            format!("fn({})", parameters.iter().map(|p| p.to_string()).collect::<Vec<String>>().join(", "))
+        },
+        value => value.to_string(),
     }
 }
 
-fn print_parse_errors(errors: &[String]){
+// The session's bindings sorted by name.
+fn format_env(env: &Env) -> String {
+    let env = env.borrow();
+    let mut names = env.names();
+    names.sort();
+    let mut out = String::new();
+    for name in names {
+        if let Some(value) = env.get(&name) {
+            out.push_str(&format!("{name} = {}\n", describe(&value)));
+        }
+    }
+    out
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".monkey_history"))
+}
+
+// Reads lines until they form a complete input, prompting for the rest
+// with `..`. Ctrl-C discards the input typed so far; Ctrl-D or the end of
+// stdin returns `None`.
+fn read_input(editor: &mut LineEditor) -> Option<String> {
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match editor.readline(&format!("{prompt} ")) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+            },
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue
+            },
+            Err(ReadlineError::Eof) => return None,
+            Err(err) => {
+                eprintln!("could not read input: {err}");
+                return None;
+            },
+        }
+        if is_complete(&input) {
+            if !input.trim().is_empty() {
+                let _ = editor.add_history_entry(input.trim_end());
+            }
+            return Some(input);
+        }
+    }
+}
+
+// Completes the word before the cursor from keywords, builtins and the
+// names bound in the session.
+struct MonkeyHelper {
+    builtins: Vec<String>,
+    env: Env,
+}
+
+// The sorted keywords, builtins and session names starting with `prefix`.
+pub fn completions(prefix: &str, builtins: &[String], env: &Env) -> Vec<String> {
+    if prefix.is_empty() {
+        return vec![];
+    }
+    let mut names: Vec<String> = KEYWORDS.iter().map(|keyword| keyword.to_string())
+        .chain(builtins.iter().cloned())
+        .chain(env.borrow().names())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Byte offset where the identifier ending at `pos` starts.
+pub fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos].char_indices().rev()
+        .take_while(|(_, ch)| ch.is_ascii_alphabetic() || *ch == '_')
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+impl Completer for MonkeyHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = word_start(line, pos);
+        Ok((start, completions(&line[start..pos], &self.builtins, &self.env)))
+    }
+}
+
+impl Hinter for MonkeyHelper {
+    type Hint = String;
+}
+
+// Colours the line as it is typed, so illegal characters and unterminated
+// strings show up in red before the input is submitted.
+impl Highlighter for MonkeyHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight::to_ansi(line))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        kind != CmdKind::MoveCursor
+    }
+}
+
+impl Validator for MonkeyHelper {}
+
+impl Helper for MonkeyHelper {}
+
+// Whether `input` is worth parsing yet. It is not while a bracket is left
+// open, a string is unterminated or the last token is an operator waiting
+// for its right operand. Stray closing brackets count as complete so the
+// parser can report them.
+fn is_complete(input: &str) -> bool {
+    let mut l = Lexer::new(input);
+    let mut depth = 0;
+    let mut last = Token::Eof;
+    loop {
+        let token = l.next_token();
+        match token {
+            Token::Eof => break,
+            Token::Lparen | Token::Lbrace | Token::Lbracket => depth += 1,
+            Token::Rparen | Token::Rbrace | Token::Rbracket => depth -= 1,
+            Token::Illegal if input.chars().nth(l.span().start) == Some('"') => return false,
+            _ => {}
+        }
+        last = token;
+    }
+
+    let awaits_operand = matches!(last,
+        Token::Plus | Token::Minus | Token::Asterisk | Token::Slash | Token::Percent | Token::Power
+        | Token::Lt | Token::Gt | Token::LtEq | Token::GtEq | Token::Eq | Token::NotEq
+        | Token::And | Token::Or | Token::Ampersand | Token::Pipe | Token::Caret
+        | Token::ShiftLeft | Token::ShiftRight | Token::Bang | Token::Tilde
+        | Token::Assign | Token::PlusAssign | Token::MinusAssign | Token::AsteriskAssign | Token::SlashAssign
+        | Token::Comma | Token::Dot
+    );
+    depth <= 0 && !awaits_operand
+}
+
+pub fn print_parse_errors(errors: &[String]){
     println!("{MONKEY_FACE}");
     println!("Whoops! We ran into some monkey business here! :)");
     for msg in errors {
         println!("{msg}");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use interpreter::capabilities::Capabilities;
+    use interpreter::evaluator::Evaluator;
+    use interpreter::lexer::Lexer;
+    use interpreter::limits::Limits;
+    use interpreter::object::{Environment, Object};
+    use interpreter::parser::Parser;
+
+    use super::{completions, format_env, format_tokens, is_complete, word_start, Session};
+
+    #[test]
+    fn test_is_complete(){
+        let tests = [
+            ("let x = 5;\n", true),
+            ("\n", true),
+            ("let add = fn(a, b) {\n", false),
+            ("let add = fn(a, b) {\n  a + b\n};\n", true),
+            ("[1, 2,\n", false),
+            ("f(1,\n  2)\n", true),
+            ("1 +\n", false),
+            ("x =\n", false),
+            ("m.\n", false),
+            ("\"abc\n", false),
+            ("\"abc\n def\"\n", true),
+            ("\"{\"\n", true),
+            ("}\n", true),
+            ("1 @\n", true),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(is_complete(input), expected, "wrong completeness for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_completions(){
+        let env = Environment::new();
+        env.borrow_mut().set("total", Object::Integer(1));
+        env.borrow_mut().set("trim_all", Object::Null);
+        let builtins = vec!["trim".to_string(), "len".to_string()];
+
+        assert_eq!(completions("tr", &builtins, &env), vec!["trim", "trim_all", "true", "try"]);
+        assert_eq!(completions("to", &builtins, &env), vec!["total"]);
+        assert_eq!(completions("fin", &builtins, &env), vec!["finally"]);
+        assert_eq!(completions("zz", &builtins, &env), Vec::<String>::new());
+        assert_eq!(completions("", &builtins, &env), Vec::<String>::new());
+
+        assert_eq!(word_start("let x = tot", 11), 8);
+        assert_eq!(word_start("f(é", 4), 4);
+        assert_eq!(word_start("", 0), 0);
+    }
+
+    #[test]
+    fn test_format_tokens(){
+        assert_eq!(format_tokens("let x =\n  5;"), "1:1\tLet\n1:5\tIdent(\"x\")\n1:7\tAssign\n2:3\tInt(\"5\")\n2:4\tSemicolon\n");
+        assert_eq!(format_tokens(""), "");
+    }
+
+    #[test]
+    fn test_format_env(){
+        let env = Environment::new();
+        let mut l = Lexer::new("let b = [1, \"two\"]; let a = fn(x, y) { x + y };");
+        let program = Parser::new(&mut l).parse_program();
+        Evaluator::new().eval_program(&program, &env);
+        env.borrow_mut().set("c", Object::Null);
+
+        assert_eq!(format_env(&env), "a = fn(x, y)\nb = [1, two]\nc = null\n");
+        assert_eq!(format_env(&Environment::new()), "");
+    }
+
+    #[test]
+    fn test_reset(){
+        let dir = std::env::temp_dir().join(format!("monkey_repl_reset_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let module = dir.join("m.mk");
+        std::fs::write(&module, "let v = 1;").unwrap();
+
+        let mut capabilities = Capabilities::default();
+        capabilities.import.grant(None);
+        let mut evaluator = Evaluator::with_capabilities(capabilities);
+        let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+        evaluator.set_limits(limits);
+        let mut session = Session::new(evaluator);
+        let import = format!("let v = (import {:?}).v;", module.display().to_string());
+        session.eval(&import, None);
+        assert_eq!(session.env.borrow().get("v"), Some(Object::Integer(1)));
+
+        // The module is loaded again after a reset, not taken from the cache.
+        std::fs::write(&module, "let v = 2;").unwrap();
+        session.run_command("reset");
+        assert_eq!(session.env.borrow().get("v"), None);
+        session.eval(&import, None);
+        assert_eq!(session.env.borrow().get("v"), Some(Object::Integer(2)));
+        assert_eq!(session.evaluator.limits(), &limits);
+        assert!(session.evaluator.capabilities().import.allows(&module));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,577 @@
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use crate::ast::{ArrayLiteral, AssignExpression, BlockStatement, Boolean, CallExpression, Expression, FloatLiteral, FunctionLiteral, Identifier, IfExpression, ImportExpression, IndexExpression, InfixExpression, IntegerLiteral, MemberExpression, PrefixExpression, Program, Statement, StringLiteral, TryExpression};
+use crate::token::{Span, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub related: Option<Span>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message, self.span)?;
+        if let Some(related) = self.related {
+            write!(f, " (declared at {})", related)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Constant,
+    Function,
+    Parameter,
+    LoopVariable,
+    CaughtError,
+}
+
+impl Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolKind::Variable => write!(f, "variable"),
+            SymbolKind::Constant => write!(f, "constant"),
+            SymbolKind::Function => write!(f, "function"),
+            SymbolKind::Parameter => write!(f, "parameter"),
+            SymbolKind::LoopVariable => write!(f, "loop variable"),
+            SymbolKind::CaughtError => write!(f, "caught error"),
+        }
+    }
+}
+
+// A name declared in the program, for editors. `detail` is what can be told
+// about the value without running it: an object type name for literals, or
+// the signature of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub span: Span,
+    pub kind: SymbolKind,
+    pub detail: Option<String>,
+    pub top_level: bool,
+}
+
+// A use of a name, with the index of the symbol it refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    pub symbol: usize,
+}
+
+#[derive(Debug)]
+struct Binding {
+    name: String,
+    span: Span,
+    used: bool,
+    // Parameters, caught errors and loop variables are never reported as
+    // unused.
+    is_parameter: bool,
+    symbol: usize,
+}
+
+// Static pass over a parsed program. Scopes follow the evaluator: the program
+// and every function body get one, blocks of `if` and loops do not.
+pub struct Resolver {
+    scopes: Vec<Vec<Binding>>,
+    // Every top-level name, so function bodies may refer to globals that are
+    // bound later in the program (they are looked up when called).
+    globals: HashSet<String>,
+    // Top-level constants, so that a function body assigning one that is
+    // declared further down is still caught.
+    global_constants: HashMap<String, Span>,
+    predeclared: HashSet<String>,
+    // Constants bound before this program, such as by earlier input in a
+    // session.
+    predeclared_constants: HashSet<String>,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+    symbols: Vec<Symbol>,
+    references: Vec<Reference>,
+    // Uses of globals from function bodies before the global is declared,
+    // matched up once the whole program is resolved.
+    late_references: Vec<(String, Span)>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            globals: HashSet::new(),
+            global_constants: HashMap::new(),
+            predeclared: HashSet::new(),
+            predeclared_constants: HashSet::new(),
+            errors: vec![],
+            warnings: vec![],
+            symbols: vec![],
+            references: vec![],
+            late_references: vec![],
+        }
+    }
+
+    // Names provided by the host that need no `let`.
+    pub fn with_globals<S: AsRef<str>>(names: &[S]) -> Self {
+        let mut r = Self::new();
+        r.predeclared = names.iter().map(|name| name.as_ref().to_string()).collect();
+        r
+    }
+
+    // Names among the globals that were bound with `const`.
+    pub fn declare_constants<S: AsRef<str>>(&mut self, names: &[S]) {
+        self.predeclared_constants.extend(names.iter().map(|name| name.as_ref().to_string()));
+    }
+
+    pub fn errors(&self) -> &Vec<Diagnostic> {
+        &self.errors
+    }
+
+    pub fn warnings(&self) -> &Vec<Diagnostic> {
+        &self.warnings
+    }
+
+    pub fn symbols(&self) -> &Vec<Symbol> {
+        &self.symbols
+    }
+
+    pub fn references(&self) -> &Vec<Reference> {
+        &self.references
+    }
+
+    pub fn resolve_program(&mut self, program: &Program) {
+        self.globals = program.statements.iter().filter_map(|stmt| match stmt {
+            Statement::Let { name, .. } => Some(name.value.clone()),
+            _ => None,
+        }).collect();
+        self.global_constants = program.statements.iter().filter_map(|stmt| match stmt {
+            Statement::Let { token: Token::Const, name, .. } => Some((name.value.clone(), name.span)),
+            _ => None,
+        }).collect();
+
+        self.scopes.push(vec![]);
+        self.resolve_statements(&program.statements);
+        self.end_scope();
+
+        for (name, span) in std::mem::take(&mut self.late_references) {
+            let symbol = self.symbols.iter().position(|symbol| symbol.top_level && symbol.name == name);
+            if let Some(symbol) = symbol {
+                self.references.push(Reference { span, symbol });
+            }
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            self.resolve_statement(stmt);
+        }
+    }
+
+    fn resolve_block(&mut self, block: &BlockStatement) {
+        self.resolve_statements(&block.statements);
+    }
+
+    fn resolve_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Let { token, name, value } => {
+                // Functions may call themselves, so their name is in scope
+                // inside the body. Anything else only sees the name after.
+                let function = value.as_any().downcast_ref::<FunctionLiteral>();
+                let kind = match (token, function) {
+                    (Token::Const, _) => SymbolKind::Constant,
+                    (_, Some(_)) => SymbolKind::Function,
+                    _ => SymbolKind::Variable,
+                };
+                let detail = infer(value.as_ref());
+                if self.scopes.len() == 1 && self.predeclared_constants.contains(&name.value) {
+                    self.errors.push(Diagnostic {
+                        message: format!("cannot redeclare constant {}", name.value),
+                        span: name.span,
+                        related: None,
+                    });
+                }
+                if function.is_some() {
+                    self.declare(name, kind, detail.clone());
+                }
+                self.resolve_expression(value.as_ref());
+                if function.is_none() {
+                    self.declare(name, kind, detail);
+                }
+            },
+            Statement::Return { token: _, value } => self.resolve_expression(value.as_ref()),
+            Statement::Expression { token: _, expression } => self.resolve_expression(expression.as_ref()),
+            Statement::While { token: _, condition, body } => {
+                self.resolve_expression(condition.as_ref());
+                self.scopes.push(vec![]);
+                self.resolve_block(body);
+                self.end_scope();
+            },
+            Statement::For { token: _, variable, iterable, body } => {
+                self.resolve_expression(iterable.as_ref());
+                self.scopes.push(vec![]);
+                self.declare(variable, SymbolKind::LoopVariable, None);
+                self.resolve_block(body);
+                self.end_scope();
+            },
+            Statement::Break { .. } | Statement::Continue { .. } | Statement::Error { .. } => {},
+            Statement::Throw { token: _, value, span: _ } => self.resolve_expression(value.as_ref()),
+        }
+    }
+
+    fn resolve_expression(&mut self, exp: &dyn Expression) {
+        let exp = exp.as_any();
+        if let Some(ident) = exp.downcast_ref::<Identifier>() {
+            self.resolve_identifier(ident);
+        } else if let Some(prefix) = exp.downcast_ref::<PrefixExpression>() {
+            self.resolve_expression(prefix.right.as_ref());
+        } else if let Some(infix) = exp.downcast_ref::<InfixExpression>() {
+            self.resolve_expression(infix.left.as_ref());
+            self.resolve_expression(infix.right.as_ref());
+        } else if let Some(assign) = exp.downcast_ref::<AssignExpression>() {
+            self.resolve_expression(assign.value.as_ref());
+            self.resolve_expression(assign.target.as_ref());
+            if let Some(ident) = assign.target.as_any().downcast_ref::<Identifier>() {
+                self.check_assignment(ident);
+            }
+        } else if let Some(if_exp) = exp.downcast_ref::<IfExpression>() {
+            self.resolve_expression(if_exp.condition.as_ref());
+            self.resolve_block(&if_exp.consequence);
+            if let Some(alt) = &if_exp.alternative {
+                self.resolve_block(alt);
+            }
+        } else if let Some(try_exp) = exp.downcast_ref::<TryExpression>() {
+            self.resolve_block(&try_exp.block);
+            if let Some(catch) = &try_exp.catch {
+                // Like a function parameter, the caught error may go unused,
+                // and it is only in scope inside the catch block.
+                self.scopes.push(vec![]);
+                self.declare(&catch.parameter, SymbolKind::CaughtError, Some("ERROR".to_string()));
+                self.resolve_block(&catch.body);
+                self.end_scope();
+            }
+            if let Some(finally) = &try_exp.finally {
+                self.resolve_block(finally);
+            }
+        } else if let Some(call) = exp.downcast_ref::<CallExpression>() {
+            self.resolve_expression(call.function.as_ref());
+            for arg in call.arguments.iter() {
+                self.resolve_expression(arg.as_ref());
+            }
+        } else if let Some(function) = exp.downcast_ref::<FunctionLiteral>() {
+            self.resolve_function(function);
+        } else if let Some(array) = exp.downcast_ref::<ArrayLiteral>() {
+            for element in array.elements.iter() {
+                self.resolve_expression(element.as_ref());
+            }
+        } else if let Some(index) = exp.downcast_ref::<IndexExpression>() {
+            self.resolve_expression(index.left.as_ref());
+            self.resolve_expression(index.index.as_ref());
+        } else if let Some(member) = exp.downcast_ref::<MemberExpression>() {
+            self.resolve_expression(member.left.as_ref());
+        }
+    }
+
+    fn resolve_function(&mut self, function: &FunctionLiteral) {
+        self.scopes.push(vec![]);
+        for (i, param) in function.parameters.iter().enumerate() {
+            if let Some(first) = function.parameters[..i].iter().find(|p| p.value == param.value) {
+                self.errors.push(Diagnostic {
+                    message: format!("duplicate parameter {}", param.value),
+                    span: param.span,
+                    related: Some(first.span),
+                });
+                continue;
+            }
+            self.declare(param, SymbolKind::Parameter, None);
+        }
+        self.resolve_block(&function.body);
+        self.end_scope();
+    }
+
+    fn resolve_identifier(&mut self, ident: &Identifier) {
+        if let Some((scope, slot)) = self.lookup(&ident.value) {
+            let binding = &mut self.scopes[scope][slot];
+            binding.used = true;
+            self.references.push(Reference { span: ident.span, symbol: binding.symbol });
+            return;
+        }
+
+        let late_global = self.scopes.len() > 1 && self.globals.contains(&ident.value);
+        if late_global {
+            self.late_references.push((ident.value.clone(), ident.span));
+        } else if !self.predeclared.contains(&ident.value) {
+            self.errors.push(Diagnostic {
+                message: format!("undefined variable {}", ident.value),
+                span: ident.span,
+                related: None,
+            });
+        }
+    }
+
+    // The parser reports assignments to constants declared before them in
+    // the same program. This catches the ones it cannot see: a function
+    // assigning a global declared further down, and constants from earlier
+    // input.
+    fn check_assignment(&mut self, ident: &Identifier) {
+        if self.lookup(&ident.value).is_some() {
+            return;
+        }
+        let related = match self.global_constants.get(&ident.value) {
+            Some(span) if self.scopes.len() > 1 => Some(*span),
+            None if self.predeclared_constants.contains(&ident.value) => None,
+            _ => return,
+        };
+        self.errors.push(Diagnostic {
+            message: format!("cannot assign to constant {}", ident.value),
+            span: ident.span,
+            related,
+        });
+    }
+
+    fn declare(&mut self, ident: &Identifier, kind: SymbolKind, detail: Option<String>) {
+        if let Some((scope, slot)) = self.lookup(&ident.value) {
+            // Declaring a name again where it was declared replaces it, which
+            // is not the same as hiding it for a while in an inner scope.
+            let message = if scope == self.scopes.len() - 1 {
+                format!("{} is already declared in this scope", ident.value)
+            } else {
+                format!("{} shadows an earlier binding", ident.value)
+            };
+            self.warnings.push(Diagnostic {
+                message,
+                span: ident.span,
+                related: Some(self.scopes[scope][slot].span),
+            });
+        }
+
+        self.symbols.push(Symbol {
+            name: ident.value.clone(),
+            span: ident.span,
+            kind,
+            detail,
+            top_level: self.scopes.len() == 1,
+        });
+        let scope = self.scopes.last_mut().expect("resolver has no scope");
+        scope.push(Binding {
+            name: ident.value.clone(),
+            span: ident.span,
+            used: false,
+            is_parameter: matches!(kind, SymbolKind::Parameter | SymbolKind::CaughtError | SymbolKind::LoopVariable),
+            symbol: self.symbols.len() - 1,
+        });
+    }
+
+    // The innermost binding of `name`, as the index of its scope and its
+    // index in that scope.
+    fn lookup(&self, name: &str) -> Option<(usize, usize)> {
+        for (scope, bindings) in self.scopes.iter().enumerate().rev() {
+            if let Some(slot) = bindings.iter().rposition(|binding| binding.name == name) {
+                return Some((scope, slot));
+            }
+        }
+        None
+    }
+
+    fn end_scope(&mut self) {
+        let is_global = self.scopes.len() == 1;
+        let scope = self.scopes.pop().expect("resolver has no scope");
+        if is_global {
+            return;
+        }
+
+        for binding in scope {
+            if !binding.used && !binding.is_parameter && !binding.name.starts_with('_') {
+                self.warnings.push(Diagnostic {
+                    message: format!("unused variable {}", binding.name),
+                    span: binding.span,
+                    related: None,
+                });
+            }
+        }
+    }
+}
+
+// The type a `let` value is known to have before it runs.
+fn infer(value: &dyn Expression) -> Option<String> {
+    let value = value.as_any();
+    if let Some(function) = value.downcast_ref::<FunctionLiteral>() {
+        let parameters: Vec<&str> = function.parameters.iter().map(|p| p.value.as_str()).collect();
+        Some(format!("fn({})", parameters.join(", ")))
+    } else if value.is::<IntegerLiteral>() {
+        Some("INTEGER".to_string())
+    } else if value.is::<FloatLiteral>() {
+        Some("FLOAT".to_string())
+    } else if value.is::<StringLiteral>() {
+        Some("STRING".to_string())
+    } else if value.is::<Boolean>() {
+        Some("BOOLEAN".to_string())
+    } else if value.is::<ArrayLiteral>() {
+        Some("ARRAY".to_string())
+    } else if value.is::<ImportExpression>() {
+        Some("MODULE".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Resolver, Symbol, SymbolKind};
+    use crate::ast::Program;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser has errors: {:?}", p.errors());
+        program
+    }
+
+    fn resolve(input: &str) -> (Vec<String>, Vec<String>) {
+        let program = parse(input);
+        let mut r = Resolver::new();
+        r.resolve_program(&program);
+        (
+            r.errors().iter().map(|d| d.to_string()).collect(),
+            r.warnings().iter().map(|d| d.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_clean_program(){
+        let (errors, warnings) = resolve("let x = 5;
+let add = fn(a, b) { a + b };
+let fact = fn(n) { if (n < 1) { 1 } else { n * fact(n - 1) } };
+let later = fn() { helper() };
+let helper = fn() { x };
+for (i in x) { add(i, x); }
+for (i in x) { let y = i; add(y, x); }
+while (x) { let y = x; add(y, x); }
+let m = import \"m.mk\";
+m.value + m.f(x);");
+        assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
+        assert!(warnings.is_empty(), "unexpected warnings: {:?}", warnings);
+    }
+
+    #[test]
+    fn test_undefined_variables(){
+        let tests = [
+            ("y;", "undefined variable y at 1:1"),
+            ("let x = x;", "undefined variable x at 1:9"),
+            ("let f = fn() { g };", "undefined variable g at 1:16"),
+            ("z + 1; let z = 1;", "undefined variable z at 1:1"),
+            ("m.x;", "undefined variable m at 1:1"),
+            ("try { 1 } catch (e) { f } ", "undefined variable f at 1:23"),
+            ("throw e;", "undefined variable e at 1:7"),
+            ("try { 1 } catch (e) { e }; e;", "undefined variable e at 1:28"),
+        ];
+
+        for (input, expected) in tests {
+            let (errors, _) = resolve(input);
+            assert_eq!(errors, vec![expected.to_string()], "wrong errors for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_parameters(){
+        let (errors, _) = resolve("fn(x, y, x) { x + y };");
+        assert_eq!(errors, vec!["duplicate parameter x at 1:10 (declared at 1:4)".to_string()]);
+    }
+
+    #[test]
+    fn test_warnings(){
+        let tests = [
+            ("fn() { let unused = 1; };", vec!["unused variable unused at 1:12"]),
+            ("fn() { let _ignored = 1; };", vec![]),
+            ("fn(x) { 1 };", vec![]),
+            ("let x = 1; fn(x) { x };", vec!["x shadows an earlier binding at 1:15 (declared at 1:5)"]),
+            ("let x = 1; let x = 2;", vec!["x is already declared in this scope at 1:16 (declared at 1:5)"]),
+            ("let x = 1; for (x in [1]) { x };", vec!["x shadows an earlier binding at 1:17 (declared at 1:5)"]),
+            ("for (i in [1]) { i }; for (i in [2]) { let j = i; j };", vec![]),
+            ("fn() { try { 1 } catch (e) { 2 } };", vec![]),
+            ("fn() { try { 1 } catch (e) { e } finally { let done = 1; } };", vec!["unused variable done at 1:48"]),
+        ];
+
+        for (input, expected) in tests {
+            let (errors, warnings) = resolve(input);
+            assert!(errors.is_empty(), "unexpected errors for {}: {:?}", input, errors);
+            assert_eq!(warnings, expected, "wrong warnings for {}", input);
+        }
+    }
+
+    #[test]
+    fn test_predeclared_globals(){
+        let program = parse("len(x);");
+        let mut r = Resolver::with_globals(&["len", "x"]);
+        r.resolve_program(&program);
+        assert!(r.errors().is_empty(), "unexpected errors: {:?}", r.errors());
+    }
+
+    #[test]
+    fn test_constants_the_parser_cannot_see(){
+        let (errors, _) = resolve("let f = fn() { x = 5; }; const x = 1;");
+        assert_eq!(errors, vec!["cannot assign to constant x at 1:16 (declared at 1:32)".to_string()]);
+
+        let program = parse("y = 2; let f = fn() { y += 1 }; let y = 3;");
+        let mut r = Resolver::with_globals(&["y"]);
+        r.declare_constants(&["y"]);
+        r.resolve_program(&program);
+        let errors: Vec<String> = r.errors().iter().map(|d| d.to_string()).collect();
+        assert_eq!(errors, vec![
+            "cannot assign to constant y at 1:1",
+            "cannot assign to constant y at 1:23",
+            "cannot redeclare constant y at 1:37",
+        ]);
+    }
+
+    #[test]
+    fn test_symbols_and_references(){
+        let program = parse("let f = fn(n) { g(n) };
+const limit = 10;
+let g = fn(x) { for (i in x) { i }; limit };
+let items = [1];
+f(items);");
+        let mut r = Resolver::new();
+        r.resolve_program(&program);
+        assert!(r.errors().is_empty(), "unexpected errors: {:?}", r.errors());
+
+        let symbols: Vec<(&str, SymbolKind, Option<&str>, bool)> = r.symbols().iter()
+            .map(|Symbol { name, kind, detail, top_level, .. }| (name.as_str(), *kind, detail.as_deref(), *top_level))
+            .collect();
+        assert_eq!(symbols, vec![
+            ("f", SymbolKind::Function, Some("fn(n)"), true),
+            ("n", SymbolKind::Parameter, None, false),
+            ("limit", SymbolKind::Constant, Some("INTEGER"), true),
+            ("g", SymbolKind::Function, Some("fn(x)"), true),
+            ("x", SymbolKind::Parameter, None, false),
+            ("i", SymbolKind::LoopVariable, None, false),
+            ("items", SymbolKind::Variable, Some("ARRAY"), true),
+        ]);
+
+        let mut references: Vec<(usize, String)> = r.references().iter()
+            .map(|reference| (reference.span.start, r.symbols()[reference.symbol].name.clone()))
+            .collect();
+        references.sort();
+        assert_eq!(references, vec![
+            (16, "g".to_string()),
+            (18, "n".to_string()),
+            (68, "x".to_string()),
+            (73, "i".to_string()),
+            (78, "limit".to_string()),
+            (104, "f".to_string()),
+            (106, "items".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,681 @@
+// A lossless syntax tree. Every char of the source is in exactly one token,
+// whitespace included, so the text of the tree is the source it was parsed
+// from and a tool can rewrite part of it while leaving the rest exactly as the
+// user wrote it.
+//
+// The tree comes in two layers. Green nodes only know their kind, their
+// children and how many chars they cover; they are immutable and shared, so an
+// edited tree reuses every subtree the edit did not touch. Syntax nodes wrap
+// green nodes with their parent and where they start in the source, and are
+// made on the way down from the root.
+//
+// Between the tokens the parser reads there is only trivia: runs of
+// whitespace, and comments, which get a token of their own so that rewrites
+// keep them.
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::ast::{Program, Statement};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::{Span, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    WhileStatement,
+    ForStatement,
+    BreakStatement,
+    ContinueStatement,
+    ThrowStatement,
+    // A statement that could not be parsed, up to where parsing picked up.
+    ErrorStatement,
+    Block,
+    Name,
+    // Numbers, strings and booleans.
+    Literal,
+    Prefix,
+    Infix,
+    Assign,
+    Paren,
+    Array,
+    If,
+    Try,
+    Catch,
+    Finally,
+    Function,
+    Call,
+    Index,
+    Member,
+    Import,
+    // Where an expression should be; it covers no chars.
+    Missing,
+    // An expression that could not be parsed.
+    Error,
+}
+
+impl SyntaxKind {
+    pub(crate) fn of_statement(stmt: &Statement) -> Self {
+        match stmt {
+            Statement::Let { .. } => Self::LetStatement,
+            Statement::Return { .. } => Self::ReturnStatement,
+            Statement::Expression { .. } => Self::ExpressionStatement,
+            Statement::While { .. } => Self::WhileStatement,
+            Statement::For { .. } => Self::ForStatement,
+            Statement::Break { .. } => Self::BreakStatement,
+            Statement::Continue { .. } => Self::ContinueStatement,
+            Statement::Throw { .. } => Self::ThrowStatement,
+            Statement::Error { .. } => Self::ErrorStatement,
+        }
+    }
+
+    // The kind of an expression that starts with `token`.
+    pub(crate) fn of_prefix(token: &Token) -> Self {
+        match token {
+            Token::Ident(_) => Self::Name,
+            Token::Int(_) | Token::Float(_) | Token::String(_) | Token::True | Token::False => Self::Literal,
+            Token::Bang | Token::Minus | Token::Tilde => Self::Prefix,
+            Token::Lparen => Self::Paren,
+            Token::Lbracket => Self::Array,
+            Token::If => Self::If,
+            Token::Try => Self::Try,
+            Token::Function => Self::Function,
+            Token::Import => Self::Import,
+            _ => Self::Error,
+        }
+    }
+
+    // The kind of an expression that continues its left operand with `token`.
+    pub(crate) fn of_infix(token: &Token) -> Self {
+        match token {
+            Token::Assign | Token::PlusAssign | Token::MinusAssign | Token::AsteriskAssign | Token::SlashAssign => Self::Assign,
+            Token::Lparen => Self::Call,
+            Token::Lbracket => Self::Index,
+            Token::Dot => Self::Member,
+            _ => Self::Infix,
+        }
+    }
+}
+
+// A token and its exact text. Whitespace has no token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenToken {
+    pub token: Option<Token>,
+    pub text: String,
+}
+
+impl GreenToken {
+    pub fn new(token: Token, text: &str) -> Self {
+        Self { token: Some(token), text: text.to_string() }
+    }
+
+    pub fn whitespace(text: &str) -> Self {
+        Self { token: None, text: text.to_string() }
+    }
+
+    // Whitespace and comments, which the parser never sees.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.token, None | Some(Token::Comment(_)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn len(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.len(),
+            GreenElement::Token(token) => token.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write_text(&self, out: &mut String) {
+        match self {
+            GreenElement::Node(node) => node.children.iter().for_each(|child| child.write_text(out)),
+            GreenElement::Token(token) => out.push_str(&token.text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let len = children.iter().map(GreenElement::len).sum();
+        Self { kind, len, children }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    // How many chars the node covers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    // A copy of the node with one child swapped. The other children are
+    // shared with this node.
+    pub fn replace_child(&self, index: usize, child: GreenElement) -> Self {
+        let mut children = self.children.clone();
+        children[index] = child;
+        Self::new(self.kind, children)
+    }
+
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        self.children.iter().for_each(|child| child.write_text(&mut out));
+        out
+    }
+}
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    // Index in the parent's children, and the char offset the node starts at.
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: GreenNode) -> Self {
+        Self(Rc::new(NodeData { green: Rc::new(green), parent: None, index: 0, offset: 0 }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &GreenNode {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    // The chars of the source the node covers.
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len
+    }
+
+    pub fn text(&self) -> String {
+        self.0.green.text()
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().enumerate().map(|(index, child)| {
+            let element = match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: Rc::clone(green),
+                    parent: Some(self.clone()),
+                    index,
+                    offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: Rc::clone(green),
+                    parent: self.clone(),
+                    index,
+                    offset,
+                }),
+            };
+            offset += child.len();
+            element
+        }).collect()
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens().into_iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }).collect()
+    }
+
+    // This node and every node under it, parents before their children.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    // Every token under the node in source order, whitespace included.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        self.children_with_tokens().into_iter().flat_map(|child| match child {
+            SyntaxElement::Node(node) => node.tokens(),
+            SyntaxElement::Token(token) => vec![token],
+        }).collect()
+    }
+
+    // The token that covers the char at `offset`.
+    pub fn token_at(&self, offset: usize) -> Option<SyntaxToken> {
+        self.tokens().into_iter().find(|token| token.range().contains(&offset))
+    }
+
+    // The innermost node that covers all of `span`, which is how an AST node
+    // with a span, such as an identifier, finds its place in the tree.
+    pub fn covering(&self, span: Span) -> SyntaxNode {
+        let mut node = self.clone();
+        while let Some(child) = node.children().into_iter().find(|child| child.range().start <= span.start && span.end <= child.range().end) {
+            node = child;
+        }
+        node
+    }
+
+    // Returns the root of a tree where this node is `green` and everything
+    // else is unchanged.
+    pub fn replace_with(&self, green: GreenNode) -> GreenNode {
+        match self.parent() {
+            Some(parent) => parent.replace_with(parent.green().replace_child(self.0.index, GreenElement::Node(Rc::new(green)))),
+            None => green,
+        }
+    }
+
+    fn fmt_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let range = self.range();
+        writeln!(f, "{}{:?}@{}..{}", "  ".repeat(depth), self.kind(), range.start, range.end)?;
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.fmt_tree(f, depth + 1)?,
+                SyntaxElement::Token(token) => writeln!(f, "{}{}", "  ".repeat(depth + 1), token)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// The tree one node or token per line, indented by depth.
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_tree(f, 0)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl SyntaxToken {
+    // None for whitespace.
+    pub fn token(&self) -> Option<&Token> {
+        self.green.token.as_ref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.green.is_trivia()
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.len()
+    }
+
+    // Returns the root of a tree where this token is `green` and everything
+    // else is unchanged.
+    pub fn replace_with(&self, green: GreenToken) -> GreenNode {
+        self.parent.replace_with(self.parent.green().replace_child(self.index, GreenElement::Token(Rc::new(green))))
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = self.range();
+        match self.token() {
+            Some(token) => write!(f, "{:?} {:?}@{}..{}", token, self.text(), range.start, range.end),
+            None => write!(f, "Whitespace {:?}@{}..{}", self.text(), range.start, range.end),
+        }
+    }
+}
+
+// Collects the tree while the parser reads tokens. A token is added when it
+// becomes the parser's current token, after the trivia before it. Parse
+// functions take a checkpoint at their first token and, once they are done,
+// wrap everything added since into a node, so nodes are only ever made from
+// finished children and a parse that gives up part way leaves its tokens in
+// the enclosing node.
+pub(crate) struct TreeBuilder {
+    children: Vec<GreenElement>,
+    // Where the last token added ends.
+    end: usize,
+    // Index of the current token in `children`.
+    current: usize,
+}
+
+impl TreeBuilder {
+    pub(crate) fn new(start: usize) -> Self {
+        Self { children: vec![], end: start, current: 0 }
+    }
+
+    // Adds the whitespace and comments up to `up_to`, one token for each
+    // comment and for each run of whitespace around them.
+    fn trivia(&mut self, lexer: &Lexer, up_to: usize) {
+        if up_to <= self.end {
+            return;
+        }
+        let source = lexer.text(self.end, up_to);
+        let text: Vec<char> = source.chars().collect();
+        let mut end = 0;
+        let mut add = |start: usize, end: usize, token: Option<Token>| {
+            let text: String = text[start..end].iter().collect();
+            self.children.push(GreenElement::Token(Rc::new(GreenToken { token, text })));
+        };
+        for (comment, span) in Lexer::new(&source).tokens() {
+            if span.start > end {
+                add(end, span.start, None);
+            }
+            add(span.start, span.end, Some(comment));
+            end = span.end;
+        }
+        if text.len() > end {
+            add(end, text.len(), None);
+        }
+        self.end = up_to;
+    }
+
+    pub(crate) fn token(&mut self, lexer: &Lexer, token: &Token, span: Span) {
+        self.trivia(lexer, span.start);
+        self.current = self.children.len();
+        if *token != Token::Eof {
+            self.children.push(GreenElement::Token(Rc::new(GreenToken::new(token.clone(), &lexer.text(span.start, span.end)))));
+            self.end = span.end;
+        }
+    }
+
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.current
+    }
+
+    pub(crate) fn wrap(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        let checkpoint = checkpoint.min(self.children.len());
+        let children = self.children.split_off(checkpoint);
+        self.children.push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+        self.current = self.current.min(checkpoint);
+    }
+
+    // Adds a node that covers no chars at `offset`, which is after the
+    // current token.
+    pub(crate) fn empty(&mut self, lexer: &Lexer, kind: SyntaxKind, offset: usize) {
+        self.trivia(lexer, offset);
+        self.children.push(GreenElement::Node(Rc::new(GreenNode::new(kind, vec![]))));
+    }
+
+    pub(crate) fn finish(mut self, lexer: &Lexer) -> GreenNode {
+        self.trivia(lexer, lexer.input_len());
+        GreenNode::new(SyntaxKind::Program, self.children)
+    }
+}
+
+// A syntax tree together with the program and errors the parser found in it.
+pub struct Parse {
+    tree: SyntaxNode,
+    program: Program,
+    errors: Vec<(String, Span)>,
+}
+
+impl Parse {
+    pub fn tree(&self) -> &SyntaxNode {
+        &self.tree
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn errors(&self) -> &Vec<(String, Span)> {
+        &self.errors
+    }
+
+    // Each statement of the program with the node it was parsed from. Every
+    // statement is a child node of the root, in the same order.
+    pub fn statements(&self) -> Vec<(&Statement, SyntaxNode)> {
+        self.program.statements.iter().zip(self.tree.children()).collect()
+    }
+}
+
+pub fn parse(source: &str) -> Parse {
+    let mut l = Lexer::new(source);
+    let mut p = Parser::with_syntax_tree(&mut l);
+    let program = p.parse_program();
+    let errors = p.errors().iter().cloned().zip(p.error_spans().iter().copied()).collect();
+    let tree = SyntaxNode::new_root(p.finish_syntax_tree().expect("parser was made to build a syntax tree"));
+    Parse { tree, program, errors }
+}
+
+// The program for a tree, which may have been rewritten since it was parsed.
+// It is derived by parsing the text of the tree again, not by walking its
+// nodes: that text is exactly what the tokens in it say, so the parser reads
+// the same tokens, and there is only one place that turns tokens into a
+// program. The cost is a full parse of the tree's text, so a tool that only
+// needs to find its way from the AST to the tree should use
+// `Parse::statements` and `SyntaxNode::covering` instead.
+pub fn program(tree: &SyntaxNode) -> Parse {
+    parse(&tree.text())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, program, GreenToken, SyntaxKind, SyntaxNode};
+    use crate::ast::{CallExpression, Identifier, Statement};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::token::Token;
+
+    #[test]
+    fn test_tree_keeps_every_char(){
+        let tests = [
+            "",
+            "  \n\t ",
+            "let x = 1;",
+            "let add = fn (a,b){\n\ta+b  \n};\r\nputs( add(1 ,2) )\n\n",
+            "try { throw \"é\" } catch (e) { e.message } finally { 1 }",
+            "while (i < 3) { i += 1; if (i == 2) { break; } else { continue } }",
+            "for (x in [1, 2.5, -3]) { m.a[0] = x ** 2 }",
+            "let s = import(\"lib.monkey\");",
+            "let x = ;\nlet = 2;\nputs(1, );\nlet y = (1 + ;\n@ \"open",
+            "fn() { let = 1; let a = 2; a",
+            "// first\nlet x = 1; // x\n\n// last",
+            "let f = fn(a) { // halves\n  a / 2 //\n};//",
+        ];
+
+        for input in tests {
+            let parsed = parse(input);
+            let tree = parsed.tree();
+            assert_eq!(tree.text(), input);
+            assert_eq!(tree.range(), 0..input.chars().count());
+            let mut end = 0;
+            for token in tree.tokens() {
+                assert_eq!(token.range().start, end, "gap before {} in {:?}", token, input);
+                end = token.range().end;
+            }
+
+            let mut l = Lexer::new(input);
+            let mut p = Parser::new(&mut l);
+            let expected = p.parse_program();
+            assert_eq!(format!("{:?}", parsed.program()), format!("{:?}", expected), "program differs for {:?}", input);
+            assert_eq!(parsed.errors().len(), p.errors().len());
+
+            assert_eq!(tree.children().len(), parsed.program().statements.len(), "statements and nodes differ for {:?}", input);
+            for (stmt, node) in parsed.statements() {
+                assert_eq!(node.kind(), SyntaxKind::of_statement(stmt), "wrong node for {} in {:?}", stmt, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_shape(){
+        let parsed = parse("let a = -b + f(1);\n");
+        let expected = "\
+Program@0..19
+  LetStatement@0..18
+    Let \"let\"@0..3
+    Whitespace \" \"@3..4
+    Ident(\"a\") \"a\"@4..5
+    Whitespace \" \"@5..6
+    Assign \"=\"@6..7
+    Whitespace \" \"@7..8
+    Infix@8..17
+      Prefix@8..10
+        Minus \"-\"@8..9
+        Name@9..10
+          Ident(\"b\") \"b\"@9..10
+      Whitespace \" \"@10..11
+      Plus \"+\"@11..12
+      Whitespace \" \"@12..13
+      Call@13..17
+        Name@13..14
+          Ident(\"f\") \"f\"@13..14
+        Lparen \"(\"@14..15
+        Literal@15..16
+          Int(\"1\") \"1\"@15..16
+        Rparen \")\"@16..17
+    Semicolon \";\"@17..18
+  Whitespace \"\\n\"@18..19
+";
+        assert_eq!(format!("{:?}", parsed.tree()), expected);
+    }
+
+    #[test]
+    fn test_comments_in_tree(){
+        let input = "let x = 1; // one\n// two\nx";
+        let parsed = parse(input);
+        let trivia: Vec<String> = parsed.tree().tokens().iter().filter(|token| token.is_trivia()).map(|token| token.to_string()).collect();
+        assert_eq!(trivia, [
+            "Whitespace \" \"@3..4",
+            "Whitespace \" \"@5..6",
+            "Whitespace \" \"@7..8",
+            "Whitespace \" \"@10..11",
+            "Comment(\"// one\") \"// one\"@11..17",
+            "Whitespace \"\\n\"@17..18",
+            "Comment(\"// two\") \"// two\"@18..24",
+            "Whitespace \"\\n\"@24..25",
+        ]);
+
+        // Renaming `x` keeps the comments where they were.
+        let mut root = parsed.tree().clone();
+        let x = Token::Ident("x".to_string());
+        while let Some(token) = root.tokens().into_iter().find(|token| token.token() == Some(&x)) {
+            root = SyntaxNode::new_root(token.replace_with(GreenToken::new(Token::Ident("y".to_string()), "y")));
+        }
+        assert_eq!(root.text(), "let y = 1; // one\n// two\ny");
+        // Parses all of the rewritten text again.
+        assert_eq!(program(&root).program().statements.len(), 2);
+    }
+
+    #[test]
+    fn test_ast_nodes_in_tree(){
+        let parsed = parse("let total = 1;\n// sum\nf(total, 2)");
+        let statements = parsed.statements();
+        assert_eq!(statements.iter().map(|(_, node)| node.text()).collect::<Vec<_>>(), ["let total = 1;", "f(total, 2)"]);
+
+        let Statement::Expression { expression: call, .. } = statements[1].0 else {
+            panic!("not an expression statement: {}", statements[1].0);
+        };
+        let call = call.as_any().downcast_ref::<CallExpression>().unwrap();
+        let argument = call.arguments[0].as_any().downcast_ref::<Identifier>().unwrap();
+        let node = parsed.tree().covering(argument.span);
+        assert_eq!((node.kind(), node.text(), node.range()), (SyntaxKind::Name, "total".to_string(), 24..29));
+        assert_eq!(parsed.tree().covering(call.span).kind(), SyntaxKind::Call);
+    }
+
+    #[test]
+    fn test_broken_input_in_tree(){
+        let parsed = parse("let x = ;\nlet = 2;");
+        let kinds: Vec<SyntaxKind> = parsed.tree().descendants().iter().map(|node| node.kind()).collect();
+        assert_eq!(kinds, [SyntaxKind::Program, SyntaxKind::LetStatement, SyntaxKind::Missing, SyntaxKind::ErrorStatement]);
+
+        let missing = &parsed.tree().descendants()[2];
+        assert_eq!(missing.range(), 8..8);
+        assert_eq!(parsed.tree().children()[1].text(), "let = 2;");
+    }
+
+    #[test]
+    fn test_rewrite_keeps_formatting(){
+        let input = "let  total = fn(a,b) {\n    a+b   \n};\ntotal(1,2)";
+        let parsed = parse(input);
+        let tree = parsed.tree();
+
+        // Renames every use of `total`, leaving all the spacing alone.
+        let mut root = tree.clone();
+        let total = Token::Ident("total".to_string());
+        while let Some(token) = root.tokens().into_iter().find(|token| token.token() == Some(&total)) {
+            root = SyntaxNode::new_root(token.replace_with(GreenToken::new(Token::Ident("sum".to_string()), "sum")));
+        }
+        assert_eq!(root.text(), "let  sum = fn(a,b) {\n    a+b   \n};\nsum(1,2)");
+
+        // The function is the same node in both trees.
+        let function = |node: &SyntaxNode| node.descendants().into_iter().find(|node| node.kind() == SyntaxKind::Function).unwrap();
+        assert!(std::ptr::eq(function(tree).green(), function(&root).green()));
+
+        // A full parse of the rewritten text.
+        let reparsed = program(&root);
+        assert_eq!(reparsed.program().statements[1].to_string(), "sum(1, 2, )");
+        assert_eq!(root.token_at(5).map(|token| token.text().to_string()), Some("sum".to_string()));
+    }
+}
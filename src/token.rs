@@ -1,4 +1,21 @@
 #![allow(dead_code)]
+use std::fmt::Display;
+
+// Location of a token in the source. `start` and `end` are char offsets,
+// `line` and `column` are 1-based and point at the first char.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
@@ -21,6 +38,7 @@ pub enum Token {
     Let,
     Ident(String),
     Int(String),
+    Float(String),
     Function, 
     True,
     False,
@@ -29,6 +47,39 @@ pub enum Token {
     Return,
     Eq,
     NotEq,
+    LtEq,
+    GtEq,
+    Percent,
+    And,
+    Or,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    Power,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    Const,
+    String(String),
+    Lbracket,
+    Rbracket,
+    Dot,
+    Import,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    // `//` up to the end of the line. Only `Lexer::tokens` returns these.
+    Comment(String),
 }
 
 impl Token {
@@ -46,13 +97,14 @@ impl Token {
             Token::Gt => ">".to_string(),
             Token::Comma => ",".to_string(),
             Token::Semicolon => ";".to_string(),
-            Token::Lparen => "{".to_string(),
-            Token::Rparen => "}".to_string(),
-            Token::Lbrace => "(".to_string(),
-            Token::Rbrace => ")".to_string(),
+            Token::Lparen => "(".to_string(),
+            Token::Rparen => ")".to_string(),
+            Token::Lbrace => "{".to_string(),
+            Token::Rbrace => "}".to_string(),
             Token::Let => "let".to_string(),
             Token::Ident(identity) => identity.to_string(),
             Token::Int(integer) => integer.to_string(),
+            Token::Float(float) => float.to_string(),
             Token::Function => "fn".to_string(), 
             Token::True => "true".to_string(),
             Token::False => "false".to_string(),
@@ -61,19 +113,68 @@ impl Token {
             Token::Return => "return".to_string(),
             Token::Eq => "==".to_string(),
             Token::NotEq => "!=".to_string(),
+            Token::LtEq => "<=".to_string(),
+            Token::GtEq => ">=".to_string(),
+            Token::Percent => "%".to_string(),
+            Token::And => "&&".to_string(),
+            Token::Or => "||".to_string(),
+            Token::Ampersand => "&".to_string(),
+            Token::Pipe => "|".to_string(),
+            Token::Caret => "^".to_string(),
+            Token::Tilde => "~".to_string(),
+            Token::ShiftLeft => "<<".to_string(),
+            Token::ShiftRight => ">>".to_string(),
+            Token::Power => "**".to_string(),
+            Token::While => "while".to_string(),
+            Token::For => "for".to_string(),
+            Token::In => "in".to_string(),
+            Token::Break => "break".to_string(),
+            Token::Continue => "continue".to_string(),
+            Token::PlusAssign => "+=".to_string(),
+            Token::MinusAssign => "-=".to_string(),
+            Token::AsteriskAssign => "*=".to_string(),
+            Token::SlashAssign => "/=".to_string(),
+            Token::Const => "const".to_string(),
+            Token::String(string) => string.to_string(),
+            Token::Lbracket => "[".to_string(),
+            Token::Rbracket => "]".to_string(),
+            Token::Dot => ".".to_string(),
+            Token::Import => "import".to_string(),
+            Token::Throw => "throw".to_string(),
+            Token::Try => "try".to_string(),
+            Token::Catch => "catch".to_string(),
+            Token::Finally => "finally".to_string(),
+            Token::Comment(text) => text.to_string(),
         }
     }
 }
 
+// Every word `lookup_ident` turns into a keyword token.
+pub const KEYWORDS: &[&str] = &[
+    "fn", "let", "const", "return", "true", "false", "if", "else", "while", "for", "in",
+    "break", "continue", "import", "throw", "try", "catch", "finally",
+];
+
 pub fn lookup_ident(literal: &str) -> Token {
     match literal {
         "fn" => Token::Function,
         "let" => Token::Let,
+        "const" => Token::Const,
         "return" => Token::Return,
         "true" => Token::True,
         "false" => Token::False,
         "if" => Token::If,
         "else" => Token::Else,
+        "while" => Token::While,
+        "for" => Token::For,
+        "in" => Token::In,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
+        "import" => Token::Import,
+        "throw" => Token::Throw,
+        "try" => Token::Try,
+        "catch" => Token::Catch,
+        "finally" => Token::Finally,
         word => Token::Ident(word.to_string())
     }
 }